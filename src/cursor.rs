@@ -0,0 +1,337 @@
+use futures::{ready, Stream};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::mem;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+use crate::error::Error;
+use crate::key::Key;
+use crate::request::Request;
+use crate::transaction::{Mode, ReadWrite, Unchecked};
+use crate::utils::to_key;
+
+/// The order a cursor visits records in.
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum CursorDirection {
+    /// In increasing key order, including duplicates.
+    #[default]
+    Next,
+    /// In increasing key order, visiting only the first record for each key.
+    NextUnique,
+    /// In decreasing key order, including duplicates.
+    Prev,
+    /// In decreasing key order, visiting only the first record for each key.
+    PrevUnique,
+}
+
+impl From<CursorDirection> for web_sys::IdbCursorDirection {
+    fn from(direction: CursorDirection) -> web_sys::IdbCursorDirection {
+        match direction {
+            CursorDirection::Next => web_sys::IdbCursorDirection::Next,
+            CursorDirection::NextUnique => web_sys::IdbCursorDirection::Nextunique,
+            CursorDirection::Prev => web_sys::IdbCursorDirection::Prev,
+            CursorDirection::PrevUnique => web_sys::IdbCursorDirection::Prevunique,
+        }
+    }
+}
+
+/// A stream over the records in an object store or index, backed by an `IDBCursor`.
+///
+/// The cursor moves on to the next record when the stream is next polled, unless it has been
+/// moved explicitly with `advance`, `continue_to` or `continue_primary_key`. `M` is what the
+/// cursor's transaction is statically known to allow (see `Mode`).
+pub struct Cursor<'a, M: Mode = Unchecked> {
+    // If we failed before opening the cursor, the error is reported on the first poll.
+    request: Result<web_sys::IdbRequest, Option<Error>>,
+    // Whether the current record has been yielded, so the cursor must move on before the next
+    // poll can return anything.
+    yielded: bool,
+    // The request fires once per step, so the callbacks are registered once and wake whichever
+    // task polled last.
+    waker: Rc<RefCell<Option<Waker>>>,
+    _onsuccess: Option<Closure<dyn FnMut()>>,
+    _onerror: Option<Closure<dyn FnMut()>>,
+    transaction: PhantomData<&'a ()>,
+    mode: PhantomData<M>,
+}
+
+impl<'a, M: Mode> Cursor<'a, M> {
+    pub(crate) fn new(request: Result<web_sys::IdbRequest, Error>) -> Self {
+        let waker = Rc::new(RefCell::new(None::<Waker>));
+        let (onsuccess, onerror) = match request {
+            Ok(ref request) => {
+                let onsuccess = notifier(&waker);
+                request.set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
+                let onerror = notifier(&waker);
+                request.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+                (Some(onsuccess), Some(onerror))
+            }
+            Err(_) => (None, None),
+        };
+        Cursor {
+            request: request.map_err(Some),
+            yielded: false,
+            waker,
+            _onsuccess: onsuccess,
+            _onerror: onerror,
+            transaction: PhantomData,
+            mode: PhantomData,
+        }
+    }
+
+    /// Skip ahead `count` records. `advance(1)` is the same as letting the cursor move on.
+    pub fn advance(&mut self, count: u32) -> Result<(), Error> {
+        self.reposition(|cursor| cursor.advance(count))
+    }
+
+    /// Move on to the next record with a key at or past `key`.
+    pub fn continue_to(&mut self, key: impl Into<Key>) -> Result<(), Error> {
+        let key = JsValue::try_from(key.into())?;
+        self.reposition(|cursor| cursor.continue_with_key(&key))
+    }
+
+    /// Move on to the next record with the given key and a primary key at or past
+    /// `primary_key`, or failing that the next record with a key past `key`.
+    ///
+    /// This is only valid for index cursors moving in the `Next` or `Prev` direction.
+    pub fn continue_primary_key(
+        &mut self,
+        key: impl Into<Key>,
+        primary_key: impl Into<Key>,
+    ) -> Result<(), Error> {
+        let key = JsValue::try_from(key.into())?;
+        let primary_key = JsValue::try_from(primary_key.into())?;
+        self.reposition(|cursor| cursor.continue_primary_key(&key, &primary_key))
+    }
+
+    /// Move the cursor on from the record it last yielded.
+    fn reposition(
+        &mut self,
+        f: impl FnOnce(&web_sys::IdbCursor) -> Result<(), JsValue>,
+    ) -> Result<(), Error> {
+        let request = match self.request {
+            Ok(ref request) if self.yielded => request,
+            _ => return Err(Error::InvalidState("the cursor is not at a record".into())),
+        };
+        let cursor: web_sys::IdbCursor = request.result()?.unchecked_into();
+        f(&cursor)?;
+        self.yielded = false;
+        Ok(())
+    }
+}
+
+/// A callback that wakes the task waiting on a cursor.
+fn notifier(waker: &Rc<RefCell<Option<Waker>>>) -> Closure<dyn FnMut()> {
+    let waker = waker.clone();
+    Closure::wrap(Box::new(move || {
+        if let Some(waker) = waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }) as Box<dyn FnMut()>)
+}
+
+// Nothing in a cursor is pinned, whatever its mode.
+impl<'a, M: Mode> Unpin for Cursor<'a, M> {}
+
+impl<'a, M: Mode> fmt::Debug for Cursor<'a, M> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Cursor")
+    }
+}
+
+impl<'a, M: Mode> Stream for Cursor<'a, M> {
+    type Item = Result<CursorEntry<'a, M>, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        use web_sys::IdbRequestReadyState as ReadyState;
+        let this = self.get_mut();
+        let request = match this.request {
+            Ok(ref request) => request,
+            Err(ref mut e) => {
+                return Poll::Ready(Some(Err(e.take().expect("cursor polled after failure"))))
+            }
+        };
+        if this.yielded {
+            let cursor: web_sys::IdbCursor = request.result()?.unchecked_into();
+            cursor.continue_()?;
+            this.yielded = false;
+        }
+        match request.ready_state() {
+            ReadyState::Pending => {
+                this.waker.borrow_mut().replace(cx.waker().clone());
+                Poll::Pending
+            }
+            ReadyState::Done => match request.result() {
+                // The cursor has run off the end of its range.
+                Ok(ref val) if val.is_null() => Poll::Ready(None),
+                Ok(val) => {
+                    let entry = CursorEntry::new(val.unchecked_into())?;
+                    this.yielded = true;
+                    Poll::Ready(Some(Ok(entry)))
+                }
+                Err(_) => Poll::Ready(Some(Err(match request.error() {
+                    Ok(Some(e)) => e.into(),
+                    Ok(None) => unreachable!("internal error polling cursor"),
+                    Err(e) => e.into(),
+                }))),
+            },
+            _ => panic!("unexpected ready state"),
+        }
+    }
+}
+
+impl<'a, M: Mode> Drop for Cursor<'a, M> {
+    fn drop(&mut self) {
+        if let Ok(ref request) = self.request {
+            // Don't leave dangling callbacks on the request.
+            request.set_onsuccess(None);
+            request.set_onerror(None);
+        }
+    }
+}
+
+/// A future for a page of records from an index, following on from a given record. See
+/// `Index::page_after`.
+#[derive(Debug)]
+pub struct Page<'a, M: Mode = Unchecked> {
+    cursor: Cursor<'a, M>,
+    last_key: Key,
+    last_primary_key: Key,
+    limit: usize,
+    entries: Vec<CursorEntry<'a, M>>,
+}
+
+impl<'a, M: Mode> Page<'a, M> {
+    pub(crate) fn new(
+        cursor: Cursor<'a, M>,
+        last_key: Key,
+        last_primary_key: Key,
+        limit: u32,
+    ) -> Self {
+        Page {
+            cursor,
+            last_key,
+            last_primary_key,
+            limit: limit as usize,
+            entries: Vec::new(),
+        }
+    }
+}
+
+impl<'a, M: Mode> Unpin for Page<'a, M> {}
+
+impl<'a, M: Mode> Future for Page<'a, M> {
+    type Output = Result<Vec<CursorEntry<'a, M>>, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        while this.entries.len() < this.limit {
+            let entry = match ready!(Pin::new(&mut this.cursor).poll_next(cx)) {
+                Some(entry) => entry?,
+                None => break,
+            };
+            let position = (entry.key(), entry.primary_key());
+            match position.cmp(&(&this.last_key, &this.last_primary_key)) {
+                // Skip straight to the last record, which we then step over.
+                Ordering::Less => this
+                    .cursor
+                    .continue_primary_key(&this.last_key, &this.last_primary_key)?,
+                Ordering::Equal => (),
+                Ordering::Greater => this.entries.push(entry),
+            }
+        }
+        Poll::Ready(Ok(mem::take(&mut this.entries)))
+    }
+}
+
+/// The record a cursor is pointing at.
+#[derive(Debug)]
+pub struct CursorEntry<'a, M: Mode = Unchecked> {
+    cursor: web_sys::IdbCursor,
+    key: Key,
+    primary_key: Key,
+    value: Option<JsValue>,
+    transaction: PhantomData<&'a ()>,
+    mode: PhantomData<M>,
+}
+
+impl<'a, M: Mode> CursorEntry<'a, M> {
+    /// Take a snapshot of where the cursor is, since it changes as the cursor moves.
+    fn new(cursor: web_sys::IdbCursor) -> Result<Self, Error> {
+        let key = Key::try_from(cursor.key()?)?;
+        let primary_key = Key::try_from(cursor.primary_key()?)?;
+        let value = match cursor.dyn_ref::<web_sys::IdbCursorWithValue>() {
+            Some(cursor) => Some(cursor.value()?),
+            None => None,
+        };
+        Ok(CursorEntry {
+            cursor,
+            key,
+            primary_key,
+            value,
+            transaction: PhantomData,
+            mode: PhantomData,
+        })
+    }
+
+    /// The key of the record. For an index this is the index key.
+    pub fn key(&self) -> &Key {
+        &self.key
+    }
+
+    /// The primary key of the record. For an object store this is the same as `key`.
+    pub fn primary_key(&self) -> &Key {
+        &self.primary_key
+    }
+
+    /// The value of the record, or `None` for a key cursor.
+    pub fn value(&self) -> Option<&JsValue> {
+        self.value.as_ref()
+    }
+
+    /// Take the value of the record, or `None` for a key cursor.
+    pub fn into_value(self) -> Option<JsValue> {
+        self.value
+    }
+}
+
+impl<'a> CursorEntry<'a, ReadWrite> {
+    /// Replace the value of this record, returning its primary key.
+    ///
+    /// This must be called before the cursor moves on.
+    pub fn update(&self, value: &JsValue) -> Request<'a, Key> {
+        let request = self
+            .check_current()
+            .and_then(|()| Ok(self.cursor.update(value)?));
+        Request::new(request, to_key)
+    }
+
+    /// Delete this record.
+    ///
+    /// This must be called before the cursor moves on.
+    pub fn delete(&self) -> Request<'a, ()> {
+        let request = self
+            .check_current()
+            .and_then(|()| Ok(self.cursor.delete()?));
+        Request::new(request, |_| Ok(()))
+    }
+
+    /// Check the cursor hasn't moved on from this record.
+    fn check_current(&self) -> Result<(), Error> {
+        let key = Key::try_from(self.cursor.key()?)?;
+        let primary_key = Key::try_from(self.cursor.primary_key()?)?;
+        if key != self.key || primary_key != self.primary_key {
+            return Err(Error::InvalidState(
+                "the cursor has moved on from this record".into(),
+            ));
+        }
+        Ok(())
+    }
+}
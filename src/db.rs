@@ -1,23 +1,32 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::Future;
 use std::mem;
 use std::ops::Deref;
+use std::pin::Pin;
+use std::rc::Rc;
 use std::sync::Arc;
-use std::marker::PhantomData;
+use std::task::{Context, Poll, Waker};
 use wasm_bindgen::prelude::*;
 use wasm_bindgen::JsCast;
 
+use crate::error::Error;
 use crate::object_store::{KeyPath, ObjectStoreDuringUpgrade};
+use crate::transaction::{
+    ReadWriteTransaction, Transaction, TransactionDuringUpgrade, TransactionMode,
+};
 
 /// A handle on the database during an upgrade.
 #[derive(Debug)]
 pub struct DbDuringUpgrade {
-    inner: web_sys::IdbDatabase,
+    db: Db,
     request: Arc<web_sys::IdbOpenDbRequest>,
 }
 
 impl Deref for DbDuringUpgrade {
     type Target = Db;
     fn deref(&self) -> &Self::Target {
-        unsafe { mem::transmute(&self.inner) }
+        &self.db
     }
 }
 
@@ -27,7 +36,9 @@ impl DbDuringUpgrade {
         request: Arc<web_sys::IdbOpenDbRequest>,
     ) -> Self {
         let inner = web_sys::IdbDatabase::unchecked_from_js(raw);
-        DbDuringUpgrade { inner, request }
+        let mut db = Db::new(inner);
+        db.close_on_drop = false;
+        DbDuringUpgrade { db, request }
     }
 
     /// Creates a new object store (roughly equivalent to a table)
@@ -36,16 +47,20 @@ impl DbDuringUpgrade {
         name: &str,
         key_path: impl Into<KeyPath>,
         auto_increment: bool,
-    ) -> Result<ObjectStoreDuringUpgrade<'a>, JsValue> {
+    ) -> Result<ObjectStoreDuringUpgrade<'a>, Error> {
         if self.store_exists(name) {
-            return Err(format!("an object store called \"{}\" already exists", name).into());
+            return Err(Error::ConstraintError(format!(
+                "an object store called \"{}\" already exists",
+                name
+            )));
         }
         let key_path: KeyPath = key_path.into();
         let key_path: JsValue = key_path.into();
-        let mut parameters = web_sys::IdbObjectStoreParameters::new();
-        parameters.key_path(Some(&key_path));
-        parameters.auto_increment(auto_increment);
+        let parameters = web_sys::IdbObjectStoreParameters::new();
+        parameters.set_key_path(&key_path);
+        parameters.set_auto_increment(auto_increment);
         let store = self
+            .db
             .inner
             .create_object_store_with_optional_parameters(name, &parameters)?;
         Ok(ObjectStoreDuringUpgrade {
@@ -55,37 +70,139 @@ impl DbDuringUpgrade {
     }
 
     /// Deletes an object store
-    pub(crate) fn delete_object_store(&self, name: &str) -> Result<(), JsValue> {
-        self.inner.delete_object_store(name)?;
+    pub(crate) fn delete_object_store(&self, name: &str) -> Result<(), Error> {
+        self.db.inner.delete_object_store(name)?;
         Ok(())
     }
 
     /// Is there already a store with the given name?
-    fn store_exists(&self, name: &str) -> bool {
+    pub(crate) fn store_exists(&self, name: &str) -> bool {
         self.object_store_names().iter().any(|test| test == name)
     }
 
-    /// Get the transaction for this request.
+    /// Get an object store that already exists, to read and write its data during the upgrade.
+    pub fn existing_object_store<'a>(
+        &'a self,
+        name: &str,
+    ) -> Result<ObjectStoreDuringUpgrade<'a>, Error> {
+        if !self.store_exists(name) {
+            return Err(Error::NotFound(format!("no object store called \"{}\"", name)));
+        }
+        let store = self.raw_transaction()?.object_store(name)?;
+        Ok(ObjectStoreDuringUpgrade {
+            inner: store,
+            db: self,
+        })
+    }
+
+    /// Get the versionchange transaction for this upgrade.
     ///
-    /// Will panic if called to early.
-    pub fn object_store(&self) -> ObjectStoreDuringUpgrade {
-        let inner = self
-            .request
-            .transaction()
-            .expect("transaction not available");
+    /// Fails if the upgrade has already finished.
+    pub fn transaction(&self) -> Result<TransactionDuringUpgrade<'_>, Error> {
+        Ok(TransactionDuringUpgrade {
+            inner: Transaction::new(self.raw_transaction()?),
+            db: self,
+        })
+    }
+
+    /// The raw versionchange transaction, if the upgrade is still running.
+    pub(crate) fn raw_transaction(&self) -> Result<web_sys::IdbTransaction, Error> {
+        let inner = self.request.transaction().ok_or_else(|| {
+            Error::InvalidState("the upgrade transaction has already finished".into())
+        })?;
         debug_assert!(inner.mode() == Ok(web_sys::IdbTransactionMode::Versionchange));
-        TransactionDuringUpgrade { inner, db: self }
+        Ok(inner)
+    }
+}
+
+/// A change to a database's version, reported to connections that stand in its way.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct VersionChange {
+    /// The version of the database before the change.
+    pub old_version: u64,
+    /// The version being upgraded to, or `None` if the database is being deleted.
+    pub new_version: Option<u64>,
+}
+
+impl<'a> From<&'a web_sys::IdbVersionChangeEvent> for VersionChange {
+    fn from(event: &'a web_sys::IdbVersionChangeEvent) -> VersionChange {
+        VersionChange {
+            old_version: event.old_version() as u64,
+            new_version: event.new_version().map(|version| version as u64),
+        }
     }
 }
 
+/// A callback for when another connection wants to change the database's version.
+type VersionChangeCallback = Box<dyn FnMut(VersionChange)>;
+
+/// The state of a connection, shared with its event callbacks.
+struct Connection {
+    // What to do when another connection wants to change the database's version.
+    on_version_change: RefCell<Option<VersionChangeCallback>>,
+    close_on_version_change: Cell<bool>,
+    // Whether the browser has closed the connection, and the `Closed` futures waiting for it.
+    closed: Cell<bool>,
+    waiting: RefCell<Vec<Waker>>,
+}
+
 /// A handle on the database
-#[derive(Debug)]
-#[repr(transparent)]
+///
+/// The connection is closed when the handle is dropped.
 pub struct Db {
     pub(crate) inner: web_sys::IdbDatabase,
+    connection: Rc<Connection>,
+    // False for the handle used during an upgrade, which shares its connection with the `Db` the
+    // open resolves to.
+    close_on_drop: bool,
+    // These must live as long as the connection can fire events.
+    onversionchange: Closure<dyn FnMut(web_sys::IdbVersionChangeEvent)>,
+    onclose: Closure<dyn FnMut()>,
 }
 
 impl Db {
+    /// Wrap a raw connection, listening for other connections that want to change its version
+    /// and for the browser closing it.
+    pub(crate) fn new(inner: web_sys::IdbDatabase) -> Self {
+        let connection = Rc::new(Connection {
+            on_version_change: RefCell::new(None),
+            close_on_version_change: Cell::new(true),
+            closed: Cell::new(false),
+            waiting: RefCell::new(Vec::new()),
+        });
+
+        let state = connection.clone();
+        let raw = inner.clone();
+        let onversionchange = Closure::wrap(Box::new(move |event: web_sys::IdbVersionChangeEvent| {
+            if let Some(ref mut callback) = *state.on_version_change.borrow_mut() {
+                callback(VersionChange::from(&event));
+            }
+            if state.close_on_version_change.get() {
+                raw.close();
+            }
+        }) as Box<dyn FnMut(web_sys::IdbVersionChangeEvent)>);
+        inner.set_onversionchange(Some(onversionchange.as_ref().unchecked_ref()));
+
+        let state = connection.clone();
+        let onclose = Closure::wrap(Box::new(move || {
+            state.closed.set(true);
+            // Take the wakers first, so a woken future can poll again straight away.
+            let waiting = mem::take(&mut *state.waiting.borrow_mut());
+            for waker in waiting {
+                waker.wake();
+            }
+        }) as Box<dyn FnMut()>);
+        inner.set_onclose(Some(onclose.as_ref().unchecked_ref()));
+
+        Db {
+            inner,
+            connection,
+            close_on_drop: true,
+            onversionchange,
+            onclose,
+        }
+    }
+
     /// The name of the database.
     pub fn name(&self) -> String {
         self.inner.name()
@@ -96,6 +213,45 @@ impl Db {
         self.inner.version() as u64
     }
 
+    /// Call `callback` when another connection wants to upgrade or delete the database.
+    ///
+    /// The other connection is blocked until this one is closed. Replaces any earlier callback.
+    pub fn on_version_change(&self, callback: impl FnMut(VersionChange) + 'static) {
+        self.connection
+            .on_version_change
+            .borrow_mut()
+            .replace(Box::new(callback));
+    }
+
+    /// Whether to close this connection when another connection wants to upgrade or delete the
+    /// database, after calling any `on_version_change` callback.
+    ///
+    /// Defaults to `true`, so that upgrades elsewhere (e.g. in another tab) are not left waiting.
+    /// Any transactions already running are allowed to finish first.
+    pub fn set_close_on_version_change(&self, close: bool) {
+        self.connection.close_on_version_change.set(close);
+    }
+
+    /// Close the connection.
+    ///
+    /// Transactions that are already running are allowed to finish, but no new ones can be
+    /// started. Dropping the handle does the same thing.
+    pub fn close(&self) {
+        self.inner.close();
+    }
+
+    /// Get a future that resolves if the browser closes the connection itself.
+    ///
+    /// This happens when the database is deleted from outside the page, e.g. because the user
+    /// cleared the site's data or the browser evicted it to free up space. The connection can't be
+    /// used after this, so the database must be opened again. The future does not resolve if the
+    /// connection is closed with `close` or by dropping the handle.
+    pub fn closed(&self) -> Closed {
+        Closed {
+            connection: self.connection.clone(),
+        }
+    }
+
     /// Get the names of the object stores in this database.
     pub fn object_store_names(&self) -> Vec<String> {
         to_collection!(self.inner.object_store_names() => Vec<String> : push)
@@ -106,15 +262,120 @@ impl Db {
     /// All operations on data happen within a transaction, including read-only operations. I'm not
     /// sure yet whether beginning a transaction takes a snapshot or whether reads might give
     /// different answers.
-    pub fn transaction<'a>(&'a self, mode: TransactionMode) -> Transaction<'a> {
+    ///
+    /// Fails if the database has no object stores, or has been closed.
+    pub fn transaction<'a>(&'a self, mode: TransactionMode) -> Result<Transaction<'a>, Error> {
+        let inner = self.inner.transaction_with_str_sequence_and_mode(
+            &self.inner.object_store_names().into(),
+            mode.into(),
+        )?;
+        Ok(Transaction::new(inner))
+    }
+
+    /// Start a database transaction over only the given object stores.
+    ///
+    /// Transactions whose scopes don't overlap can run concurrently, so prefer this over
+    /// `transaction` when you know which stores you need.
+    pub fn transaction_on<'a>(
+        &'a self,
+        store_names: &[&str],
+        mode: TransactionMode,
+    ) -> Result<Transaction<'a>, Error> {
+        let existing = self.object_store_names();
+        let names = js_sys::Array::new();
+        for name in store_names {
+            if !existing.iter().any(|test| test == name) {
+                return Err(Error::NotFound(format!("no object store called \"{}\"", name)));
+            }
+            names.push(&JsValue::from(*name));
+        }
         let inner = self
             .inner
-            .transaction_with_str_sequence_and_mode(
-                &self.inner.object_store_names().into(),
-                mode.into(),
+            .transaction_with_str_sequence_and_mode(&names, mode.into())?;
+        Ok(Transaction::new(inner))
+    }
+
+    /// Start a database transaction over a single object store.
+    pub fn transaction_on_store<'a>(
+        &'a self,
+        store_name: &str,
+        mode: TransactionMode,
+    ) -> Result<Transaction<'a>, Error> {
+        self.transaction_on(&[store_name], mode)
+    }
+
+    /// Start a read-write transaction over all the object stores, whose handles can write through
+    /// cursors.
+    pub fn read_write_transaction<'a>(&'a self) -> Result<ReadWriteTransaction<'a>, Error> {
+        self.transaction(TransactionMode::ReadWrite)?.into_read_write()
+    }
+
+    /// Start a read-write transaction over only the given object stores, whose handles can write
+    /// through cursors.
+    pub fn read_write_transaction_on<'a>(
+        &'a self,
+        store_names: &[&str],
+    ) -> Result<ReadWriteTransaction<'a>, Error> {
+        self.transaction_on(store_names, TransactionMode::ReadWrite)?
+            .into_read_write()
+    }
+}
+
+impl fmt::Debug for Db {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Db")
+            .field("inner", &self.inner)
+            .field(
+                "close_on_version_change",
+                &self.connection.close_on_version_change.get(),
             )
-            .unwrap();
-        Transaction { inner, db: PhantomData }
+            .field("closed", &self.connection.closed.get())
+            .finish()
+    }
+}
+
+impl Drop for Db {
+    fn drop(&mut self) {
+        if self.close_on_drop {
+            self.inner.close();
+        }
+        // The upgrade and the opened database share a connection, so only remove our own
+        // callbacks.
+        let ours: &JsValue = self.onversionchange.as_ref();
+        if self.inner.onversionchange().map(JsValue::from).as_ref() == Some(ours) {
+            self.inner.set_onversionchange(None);
+        }
+        let ours: &JsValue = self.onclose.as_ref();
+        if self.inner.onclose().map(JsValue::from).as_ref() == Some(ours) {
+            self.inner.set_onclose(None);
+        }
     }
 }
 
+/// A future that resolves when the browser closes a connection, returned by `Db::closed`.
+pub struct Closed {
+    connection: Rc<Connection>,
+}
+
+impl fmt::Debug for Closed {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Closed")
+            .field("closed", &self.connection.closed.get())
+            .finish()
+    }
+}
+
+impl Future for Closed {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        if self.connection.closed.get() {
+            return Poll::Ready(());
+        }
+        let mut waiting = self.connection.waiting.borrow_mut();
+        if !waiting.iter().any(|waker| waker.will_wake(cx.waker())) {
+            waiting.push(cx.waker().clone());
+        }
+        Poll::Pending
+    }
+}
@@ -0,0 +1,167 @@
+use std::error::Error as StdError;
+use std::fmt;
+use wasm_bindgen::{JsCast, JsValue};
+
+/// An error from IndexedDB.
+///
+/// Errors raised by the browser are decoded from the name of their `DOMException`. Each variant
+/// carries the message describing what went wrong.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Error {
+    /// A constraint was not satisfied, e.g. adding a record whose key already exists, or creating
+    /// an object store or index with a name that is taken.
+    ConstraintError(String),
+    /// Data passed to an operation was invalid, e.g. an invalid key, or a key given for a store
+    /// that uses in-line keys.
+    DataError(String),
+    /// A value could not be cloned to be stored.
+    DataCloneError(String),
+    /// The browser refused to store more data.
+    QuotaExceeded(String),
+    /// The database was opened at a version lower than its current version.
+    VersionError(String),
+    /// The transaction was aborted.
+    Abort(String),
+    /// A request was made against a transaction that has already finished.
+    TransactionInactive(String),
+    /// A write was attempted in a read-only transaction.
+    ReadOnly(String),
+    /// An operation was called on an object in the wrong state, e.g. a cursor that has moved on.
+    InvalidState(String),
+    /// An operation was not valid for the object it was called on.
+    InvalidAccess(String),
+    /// The requested object store or index does not exist.
+    NotFound(String),
+    /// Any other error.
+    Unknown {
+        /// The name of the error, e.g. `TypeError`.
+        name: String,
+        /// What went wrong.
+        message: String,
+    },
+}
+
+impl Error {
+    /// A `TypeError`, which is what the browser raises for arguments of the wrong kind.
+    pub(crate) fn type_error(message: impl Into<String>) -> Error {
+        Error::Unknown {
+            name: "TypeError".to_owned(),
+            message: message.into(),
+        }
+    }
+
+    /// Decode an error from the name of a `DOMException` (or javascript error) and its message.
+    pub fn from_name(name: &str, message: String) -> Error {
+        match name {
+            "ConstraintError" => Error::ConstraintError(message),
+            "DataError" => Error::DataError(message),
+            "DataCloneError" => Error::DataCloneError(message),
+            "QuotaExceededError" => Error::QuotaExceeded(message),
+            "VersionError" => Error::VersionError(message),
+            "AbortError" => Error::Abort(message),
+            "TransactionInactiveError" => Error::TransactionInactive(message),
+            "ReadOnlyError" => Error::ReadOnly(message),
+            "InvalidStateError" => Error::InvalidState(message),
+            "InvalidAccessError" => Error::InvalidAccess(message),
+            "NotFoundError" => Error::NotFound(message),
+            _ => Error::Unknown {
+                name: name.to_owned(),
+                message,
+            },
+        }
+    }
+
+    /// The name of the error, matching the `DOMException` name the browser would use.
+    pub fn name(&self) -> &str {
+        match self {
+            Error::ConstraintError(_) => "ConstraintError",
+            Error::DataError(_) => "DataError",
+            Error::DataCloneError(_) => "DataCloneError",
+            Error::QuotaExceeded(_) => "QuotaExceededError",
+            Error::VersionError(_) => "VersionError",
+            Error::Abort(_) => "AbortError",
+            Error::TransactionInactive(_) => "TransactionInactiveError",
+            Error::ReadOnly(_) => "ReadOnlyError",
+            Error::InvalidState(_) => "InvalidStateError",
+            Error::InvalidAccess(_) => "InvalidAccessError",
+            Error::NotFound(_) => "NotFoundError",
+            Error::Unknown { name, .. } => name,
+        }
+    }
+
+    /// The message describing what went wrong.
+    pub fn message(&self) -> &str {
+        match self {
+            Error::ConstraintError(message)
+            | Error::DataError(message)
+            | Error::DataCloneError(message)
+            | Error::QuotaExceeded(message)
+            | Error::VersionError(message)
+            | Error::Abort(message)
+            | Error::TransactionInactive(message)
+            | Error::ReadOnly(message)
+            | Error::InvalidState(message)
+            | Error::InvalidAccess(message)
+            | Error::NotFound(message)
+            | Error::Unknown { message, .. } => message,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.name(), self.message())
+    }
+}
+
+impl StdError for Error {}
+
+impl From<web_sys::DomException> for Error {
+    fn from(e: web_sys::DomException) -> Error {
+        Error::from_name(&e.name(), e.message())
+    }
+}
+
+impl From<JsValue> for Error {
+    fn from(val: JsValue) -> Error {
+        if val.is_instance_of::<web_sys::DomException>() {
+            return val.unchecked_into::<web_sys::DomException>().into();
+        }
+        if val.is_instance_of::<js_sys::Error>() {
+            let e: js_sys::Error = val.unchecked_into();
+            return Error::from_name(&String::from(e.name()), String::from(e.message()));
+        }
+        let message = match val.as_string() {
+            Some(message) => message,
+            None => format!("{:?}", val),
+        };
+        Error::Unknown {
+            name: "Error".to_owned(),
+            message,
+        }
+    }
+}
+
+impl From<Error> for JsValue {
+    fn from(e: Error) -> JsValue {
+        match web_sys::DomException::new_with_message_and_name(e.message(), e.name()) {
+            Ok(e) => e.into(),
+            Err(e) => e,
+        }
+    }
+}
+
+#[test]
+fn test_from_name() {
+    let e = Error::from_name("ConstraintError", "key already exists".into());
+    assert_eq!(e, Error::ConstraintError("key already exists".into()));
+    assert_eq!(e.name(), "ConstraintError");
+    assert_eq!(e.to_string(), "ConstraintError: key already exists");
+    assert_eq!(
+        Error::from_name("QuotaExceededError", "full".into()),
+        Error::QuotaExceeded("full".into())
+    );
+    let e = Error::from_name("TypeError", "oops".into());
+    assert_eq!(e.name(), "TypeError");
+    assert_eq!(e.message(), "oops");
+}
@@ -0,0 +1,176 @@
+use futures::{future, TryFutureExt};
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+use crate::db::VersionChange;
+use crate::error::Error;
+use crate::key::Key;
+use crate::request::Request;
+use crate::utils::to_vec;
+use crate::OpenDb;
+
+/// The entry point to IndexedDB, wrapping `IDBFactory`.
+#[derive(Debug, Clone)]
+pub struct Factory {
+    pub(crate) inner: web_sys::IdbFactory,
+}
+
+impl Factory {
+    /// Get the factory for the current global scope.
+    ///
+    /// This works in a window and in dedicated, shared and service workers. Anywhere else, it
+    /// falls back to `globalThis.indexedDB`.
+    pub fn get() -> Result<Factory, Error> {
+        let global = js_sys::global();
+        let inner = if let Some(window) = global.dyn_ref::<web_sys::Window>() {
+            window.indexed_db()?
+        } else if let Some(worker) = global.dyn_ref::<web_sys::WorkerGlobalScope>() {
+            worker.indexed_db()?
+        } else {
+            js_sys::Reflect::get(&global, &JsValue::from("indexedDB"))?
+                .dyn_into::<web_sys::IdbFactory>()
+                .ok()
+        };
+        let inner = inner.ok_or_else(|| Error::NotFound("indexeddb is not available".into()))?;
+        Ok(Factory { inner })
+    }
+
+    /// Wrap a factory obtained some other way, e.g. from a polyfill or another global scope.
+    pub fn from_raw(inner: web_sys::IdbFactory) -> Factory {
+        Factory { inner }
+    }
+
+    /// Compare two keys using the browser's `indexedDB.cmp`.
+    ///
+    /// `Key`'s `Ord` gives the same answer without a round-trip. Fails with a `DataError` if
+    /// either key is NaN.
+    pub fn cmp(&self, first: &Key, second: &Key) -> Result<Ordering, Error> {
+        let ordering = self
+            .inner
+            .cmp(&JsValue::try_from(first)?, &JsValue::try_from(second)?)?;
+        Ok(ordering.cmp(&0))
+    }
+
+    /// Open an existing database at its current version, without upgrading it.
+    ///
+    /// Fails with a `NotFound` error if there is no database called `name`.
+    pub fn open_latest(&self, name: &str) -> OpenDb {
+        let missing = format!("no database called \"{}\"", name);
+        let request = self.inner.open(name).map_err(Error::from);
+        // Opening a database that doesn't exist creates it, so undo that.
+        crate::open_request(request, move |_, _, upgrade| {
+            upgrade.fail(Error::NotFound(missing.clone()))
+        })
+    }
+
+    /// Delete a database.
+    ///
+    /// Open connections to the database are asked to close first (see `Db::on_version_change`),
+    /// and the delete waits until they have. Deleting a database that doesn't exist succeeds.
+    pub fn delete_database(&self, name: &str) -> DeleteDb {
+        let raw = self.inner.delete_database(name).map_err(Error::from);
+        DeleteDb {
+            // A successful delete's result is `undefined`.
+            request: Request::new(raw.clone().map(Into::into), |_| Ok(())),
+            raw: raw.ok(),
+            onblocked: None,
+        }
+    }
+
+    /// List the databases available to the page.
+    ///
+    /// Not all browsers support this: where they don't, the future fails with a `NotFound` error.
+    pub fn databases(&self) -> impl Future<Output = Result<Vec<DatabaseInfo>, Error>> + Unpin {
+        let promise = js_sys::Reflect::get(&self.inner, &JsValue::from("databases"))
+            .ok()
+            .and_then(|databases| databases.dyn_into::<js_sys::Function>().ok())
+            .ok_or_else(|| Error::NotFound("listing databases is not supported".into()))
+            .and_then(|databases| Ok(databases.call0(&self.inner)?))
+            .and_then(|promise| Ok(promise.dyn_into::<js_sys::Promise>()?));
+        future::ready(promise)
+            .and_then(|promise| wasm_bindgen_futures::JsFuture::from(promise).err_into())
+            .and_then(|list| {
+                future::ready(to_vec(list).and_then(|list| {
+                    list.iter().map(DatabaseInfo::from_js).collect()
+                }))
+            })
+    }
+}
+
+/// The name and version of a database, as listed by `Factory::databases`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct DatabaseInfo {
+    /// The name of the database.
+    pub name: String,
+    /// The current version of the database.
+    pub version: u64,
+}
+
+impl DatabaseInfo {
+    fn from_js(val: &JsValue) -> Result<DatabaseInfo, Error> {
+        let name = js_sys::Reflect::get(val, &JsValue::from("name"))?.as_string();
+        let version = js_sys::Reflect::get(val, &JsValue::from("version"))?.as_f64();
+        match (name, version) {
+            (Some(name), Some(version)) => Ok(DatabaseInfo {
+                name,
+                version: version as u64,
+            }),
+            _ => Err(Error::type_error(format!(
+                "expected a database name and version, found {:?}",
+                val
+            ))),
+        }
+    }
+}
+
+/// A future that deletes a database, returned by `Factory::delete_database`.
+pub struct DeleteDb {
+    request: Request<'static, ()>,
+    // The raw request, if the delete could be started, to listen for it being blocked.
+    raw: Option<web_sys::IdbOpenDbRequest>,
+    onblocked: Option<Closure<dyn FnMut(web_sys::IdbVersionChangeEvent)>>,
+}
+
+impl DeleteDb {
+    /// Call `on_blocked` if the delete has to wait for open connections to the database to close.
+    pub fn on_blocked(mut self, mut on_blocked: impl FnMut(VersionChange) + 'static) -> Self {
+        if let Some(ref raw) = self.raw {
+            let onblocked = Closure::wrap(Box::new(move |event: web_sys::IdbVersionChangeEvent| {
+                on_blocked(VersionChange::from(&event))
+            }) as Box<dyn FnMut(web_sys::IdbVersionChangeEvent)>);
+            raw.set_onblocked(Some(onblocked.as_ref().unchecked_ref()));
+            self.onblocked.replace(onblocked);
+        }
+        self
+    }
+}
+
+impl fmt::Debug for DeleteDb {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DeleteDb")
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+impl Drop for DeleteDb {
+    fn drop(&mut self) {
+        // Don't leave a dangling callback on the request.
+        if let Some(ref raw) = self.raw {
+            raw.set_onblocked(None);
+        }
+    }
+}
+
+impl Future for DeleteDb {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.request).poll(cx)
+    }
+}
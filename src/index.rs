@@ -1,9 +1,72 @@
+use std::convert::TryFrom;
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::Deref;
 use wasm_bindgen::prelude::*;
 
 use crate::object_store::{ObjectStore, ObjectStoreDuringUpgrade};
+use crate::request::Request;
+use crate::cursor::{Cursor, CursorDirection, Page};
+use crate::error::Error;
+use crate::key::Key;
+use crate::key_range::{KeyRange, Query};
+use crate::object_store::KeyPath;
+use crate::transaction::{Mode, ReadWrite, Unchecked};
+use crate::utils::{to_count, to_keys, to_option, to_option_key, to_vec};
+
+/// Options for creating an index, built up from `IndexOptions::new()`.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct IndexOptions {
+    unique: bool,
+    multi_entry: bool,
+    locale: Option<String>,
+}
+
+impl IndexOptions {
+    /// The default options: not unique, not multi-entry, and not locale-aware.
+    pub fn new() -> Self {
+        IndexOptions::default()
+    }
+
+    /// Whether no two records may share a key in the index.
+    pub fn unique(mut self, unique: bool) -> Self {
+        self.unique = unique;
+        self
+    }
+
+    /// Whether a key path that resolves to an array adds an entry for each element of the array,
+    /// rather than one entry for the whole array. Not allowed with a compound key path.
+    pub fn multi_entry(mut self, multi_entry: bool) -> Self {
+        self.multi_entry = multi_entry;
+        self
+    }
+
+    /// Sort string keys by the collation rules of the given locale, or the user's locale if it
+    /// is `"auto"`. Only some browsers support this, and the rest ignore it.
+    pub fn locale(mut self, locale: impl Into<String>) -> Self {
+        self.locale = Some(locale.into());
+        self
+    }
+
+    pub(crate) fn to_raw(&self) -> web_sys::IdbIndexParameters {
+        let params = web_sys::IdbIndexParameters::new();
+        params.set_unique(self.unique);
+        params.set_multi_entry(self.multi_entry);
+        if let Some(ref locale) = self.locale {
+            // Locale-aware indexes are a non-standard extension, so web-sys marks them deprecated.
+            #[allow(deprecated)]
+            params.set_locale(Some(locale));
+        }
+        params
+    }
+}
+
+impl From<bool> for IndexOptions {
+    /// Options for an index that is unique or not.
+    fn from(unique: bool) -> IndexOptions {
+        IndexOptions::new().unique(unique)
+    }
+}
 
 /// An index during a database upgrade
 #[derive(Debug)]
@@ -13,7 +76,7 @@ pub struct IndexDuringUpgrade<'a> {
 }
 
 impl<'a> Deref for IndexDuringUpgrade<'a> {
-    type Target = Index<'a>;
+    type Target = Index<'a, ReadWrite>;
     fn deref(&self) -> &Self::Target {
         unsafe { mem::transmute(&self.inner) }
     }
@@ -25,28 +88,193 @@ impl<'a> IndexDuringUpgrade<'a> {
     }
 
     /// Deletes the index.
-    pub fn delete(self) -> Result<(), JsValue> {
+    pub fn delete(self) -> Result<(), Error> {
         self.parent.delete_index(&self.name())
     }
+
+    /// Renames the index.
+    ///
+    /// Fails if the object store already has another index with the new name.
+    pub fn rename(&self, new_name: &str) -> Result<(), Error> {
+        if new_name == self.name() {
+            return Ok(());
+        }
+        if self.parent.index_names().contains(new_name) {
+            return Err(Error::ConstraintError(format!(
+                "an index called \"{}\" already exists on object store \"{}\"",
+                new_name,
+                self.parent.name()
+            )));
+        }
+        self.inner.set_name(new_name);
+        Ok(())
+    }
 }
 
 /// An index
+///
+/// `M` is what the index's transaction is statically known to allow (see `Mode`).
 #[repr(transparent)]
 #[derive(Debug)]
-pub struct Index<'a> {
+pub struct Index<'a, M: Mode = Unchecked> {
     inner: web_sys::IdbIndex,
     parent: PhantomData<&'a ()>,
+    mode: PhantomData<M>,
 }
 
-impl<'a> Index<'a> {
-    pub(crate) fn new(inner: web_sys::IdbIndex, _: &'a ObjectStore<'a>) -> Self {
+impl<'a, M: Mode> Index<'a, M> {
+    pub(crate) fn new(inner: web_sys::IdbIndex, _: &'a ObjectStore<'a, M>) -> Self {
         Index {
             inner,
             parent: PhantomData,
+            mode: PhantomData,
         }
     }
 
     pub fn name(&self) -> String {
         self.inner.name()
     }
+
+    /// The name of the object store this index belongs to.
+    pub fn object_store_name(&self) -> String {
+        self.inner.object_store().name()
+    }
+
+    /// The key path of the index, which picks the index key out of each record.
+    pub fn key_path(&self) -> Result<KeyPath, Error> {
+        KeyPath::try_from(self.inner.key_path()?)
+    }
+
+    /// Whether no two records may share a key in the index.
+    pub fn unique(&self) -> bool {
+        self.inner.unique()
+    }
+
+    /// Whether array keys add an entry for each of their elements.
+    pub fn multi_entry(&self) -> bool {
+        self.inner.multi_entry()
+    }
+
+    /// The locale used to sort string keys, if the index is locale-aware and the browser supports
+    /// it.
+    #[allow(deprecated)] // a non-standard extension, see `IndexOptions::locale`
+    pub fn locale(&self) -> Option<String> {
+        self.inner.locale()
+    }
+
+    /// Get the value of the first record in the index matching the query (a key or key range).
+    pub fn get(&self, query: impl Into<Query>) -> Request<'a, Option<JsValue>> {
+        let request = query
+            .into()
+            .to_js()
+            .and_then(|query| Ok(self.inner.get(&query)?));
+        Request::new(request, to_option)
+    }
+
+    /// Get the primary key of the first record in the index matching the query (a key or key
+    /// range).
+    pub fn get_key(&self, query: impl Into<Query>) -> Request<'a, Option<Key>> {
+        let request = query
+            .into()
+            .to_js()
+            .and_then(|query| Ok(self.inner.get_key(&query)?));
+        Request::new(request, to_option_key)
+    }
+
+    /// Get the values of all records in the index matching the query, up to `count` of them.
+    pub fn get_all(
+        &self,
+        query: impl Into<Query>,
+        count: Option<u32>,
+    ) -> Request<'a, Vec<JsValue>> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(match count {
+                Some(count) => self.inner.get_all_with_key_and_limit(&query, count)?,
+                None => self.inner.get_all_with_key(&query)?,
+            })
+        });
+        Request::new(request, to_vec)
+    }
+
+    /// Get the primary keys of all records in the index matching the query, up to `count` of them.
+    pub fn get_all_keys(
+        &self,
+        query: impl Into<Query>,
+        count: Option<u32>,
+    ) -> Request<'a, Vec<Key>> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(match count {
+                Some(count) => self.inner.get_all_keys_with_key_and_limit(&query, count)?,
+                None => self.inner.get_all_keys_with_key(&query)?,
+            })
+        });
+        Request::new(request, to_keys)
+    }
+
+    /// Open a cursor over the records in the index matching the query, visiting them in the
+    /// given direction.
+    pub fn open_cursor(
+        &self,
+        query: impl Into<Query>,
+        direction: CursorDirection,
+    ) -> Cursor<'a, M> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(self
+                .inner
+                .open_cursor_with_range_and_direction(&query, direction.into())?)
+        });
+        Cursor::new(request)
+    }
+
+    /// Open a cursor over the keys of the records in the index matching the query, visiting them
+    /// in the given direction. This avoids loading the values.
+    pub fn open_key_cursor(
+        &self,
+        query: impl Into<Query>,
+        direction: CursorDirection,
+    ) -> Cursor<'a, M> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(self
+                .inner
+                .open_key_cursor_with_range_and_direction(&query, direction.into())?)
+        });
+        Cursor::new(request)
+    }
+
+    /// Get the next `limit` records in the index after the record with key `last_key` and
+    /// primary key `last_primary_key`, in increasing order.
+    ///
+    /// Pass the key and primary key of the last entry of one page to get the next page. Unlike
+    /// paging with offsets, this stays correct when records are added or removed in between.
+    pub fn page_after(
+        &self,
+        last_key: impl Into<Key>,
+        last_primary_key: impl Into<Key>,
+        limit: u32,
+    ) -> Page<'a, M> {
+        let last_key = last_key.into();
+        let cursor = self.open_cursor(
+            KeyRange::lower_bound(last_key.clone(), false),
+            CursorDirection::Next,
+        );
+        Page::new(cursor, last_key, last_primary_key.into(), limit)
+    }
+
+    /// Count the records in the index matching the query.
+    pub fn count(&self, query: impl Into<Query>) -> Request<'a, u32> {
+        let request = query
+            .into()
+            .to_js()
+            .and_then(|query| Ok(self.inner.count_with_key(&query)?));
+        Request::new(request, to_count)
+    }
+}
+
+#[test]
+fn test_index_options() {
+    assert_eq!(IndexOptions::from(true), IndexOptions::new().unique(true));
+    assert_eq!(IndexOptions::from(false), IndexOptions::new());
+    let options = IndexOptions::new().multi_entry(true).locale("auto");
+    assert!(options.multi_entry && !options.unique);
+    assert_eq!(options.locale, Some("auto".to_string()));
 }
@@ -0,0 +1,313 @@
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::time::{SystemTime, UNIX_EPOCH};
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::error::Error;
+
+/// A key in an object store or index.
+///
+/// IndexedDB only accepts a subset of javascript values as keys. This type models exactly that
+/// subset, so invalid keys are caught when they are built rather than when they reach the
+/// database. Numbers and dates must not be NaN: use the checked constructors `Key::number` and
+/// `Key::date` if that isn't already known. A NaN key can still be built directly, but it is
+/// rejected with a `DataError` when it is converted to a javascript value.
+///
+/// Keys are ordered as IndexedDB orders them (see `indexedDB.cmp`): arrays sort after binary data,
+/// which sorts after strings, then dates, then numbers. So that the ordering stays total, NaN
+/// sorts after every other number or date and equal to itself.
+#[derive(Debug, Clone)]
+pub enum Key {
+    /// A number.
+    Number(f64),
+    /// A date, as milliseconds since the Unix epoch.
+    Date(f64),
+    /// A string.
+    String(String),
+    /// Binary data (stored as an `ArrayBuffer`).
+    Binary(Vec<u8>),
+    /// An array of keys.
+    Array(Vec<Key>),
+}
+
+impl Key {
+    /// A number key, or `None` if the number is NaN.
+    pub fn number(val: f64) -> Option<Key> {
+        if val.is_nan() {
+            None
+        } else {
+            Some(Key::Number(val))
+        }
+    }
+
+    /// A date key from milliseconds since the Unix epoch, or `None` if the time is NaN.
+    pub fn date(millis: f64) -> Option<Key> {
+        if millis.is_nan() {
+            None
+        } else {
+            Some(Key::Date(millis))
+        }
+    }
+}
+
+impl Key {
+    /// The rank of this key's type in the ordering between types.
+    fn type_rank(&self) -> u8 {
+        match self {
+            Key::Number(_) => 0,
+            Key::Date(_) => 1,
+            Key::String(_) => 2,
+            Key::Binary(_) => 3,
+            Key::Array(_) => 4,
+        }
+    }
+}
+
+impl Ord for Key {
+    fn cmp(&self, other: &Key) -> Ordering {
+        match (self, other) {
+            (Key::Number(a), Key::Number(b)) | (Key::Date(a), Key::Date(b)) => {
+                match (a.is_nan(), b.is_nan()) {
+                    (false, false) => a.partial_cmp(b).expect("neither number is NaN"),
+                    (nan_a, nan_b) => nan_a.cmp(&nan_b),
+                }
+            }
+            // Strings are compared by UTF-16 code unit, not by code point.
+            (Key::String(a), Key::String(b)) => a.encode_utf16().cmp(b.encode_utf16()),
+            (Key::Binary(a), Key::Binary(b)) => a.cmp(b),
+            (Key::Array(a), Key::Array(b)) => a.cmp(b),
+            (a, b) => a.type_rank().cmp(&b.type_rank()),
+        }
+    }
+}
+
+impl PartialOrd for Key {
+    fn partial_cmp(&self, other: &Key) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Key {
+    fn eq(&self, other: &Key) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Key {}
+
+macro_rules! key_from_number {
+    ($($ty:ty),*) => {
+        $(
+            impl From<$ty> for Key {
+                fn from(val: $ty) -> Key {
+                    Key::Number(val as f64)
+                }
+            }
+        )*
+    };
+}
+
+// Only integer types that fit exactly in an `f64`.
+key_from_number!(i8, i16, i32, u8, u16, u32);
+
+impl TryFrom<f32> for Key {
+    type Error = Error;
+
+    /// Fails with a `DataError` if the number is NaN.
+    fn try_from(val: f32) -> Result<Key, Error> {
+        Key::try_from(val as f64)
+    }
+}
+
+impl TryFrom<f64> for Key {
+    type Error = Error;
+
+    /// Fails with a `DataError` if the number is NaN.
+    fn try_from(val: f64) -> Result<Key, Error> {
+        Key::number(val).ok_or_else(|| Error::DataError("NaN is not a valid key".into()))
+    }
+}
+
+impl From<SystemTime> for Key {
+    fn from(time: SystemTime) -> Key {
+        let millis = match time.duration_since(UNIX_EPOCH) {
+            Ok(since) => since.as_secs() as f64 * 1000.0 + since.subsec_nanos() as f64 / 1e6,
+            Err(e) => {
+                let before = e.duration();
+                -(before.as_secs() as f64 * 1000.0 + before.subsec_nanos() as f64 / 1e6)
+            }
+        };
+        Key::Date(millis)
+    }
+}
+
+impl From<String> for Key {
+    fn from(val: String) -> Key {
+        Key::String(val)
+    }
+}
+
+impl<'a> From<&'a str> for Key {
+    fn from(val: &'a str) -> Key {
+        Key::String(val.to_owned())
+    }
+}
+
+impl From<Vec<u8>> for Key {
+    fn from(val: Vec<u8>) -> Key {
+        Key::Binary(val)
+    }
+}
+
+impl<'a> From<&'a [u8]> for Key {
+    fn from(val: &'a [u8]) -> Key {
+        Key::Binary(val.to_owned())
+    }
+}
+
+impl From<Vec<Key>> for Key {
+    fn from(val: Vec<Key>) -> Key {
+        Key::Array(val)
+    }
+}
+
+impl<'a> From<&'a Key> for Key {
+    fn from(val: &'a Key) -> Key {
+        val.clone()
+    }
+}
+
+impl TryFrom<Key> for JsValue {
+    type Error = Error;
+
+    fn try_from(key: Key) -> Result<JsValue, Error> {
+        JsValue::try_from(&key)
+    }
+}
+
+impl<'a> TryFrom<&'a Key> for JsValue {
+    type Error = Error;
+
+    /// Fails with a `DataError` if the key is or contains NaN.
+    fn try_from(key: &'a Key) -> Result<JsValue, Error> {
+        Ok(match key {
+            Key::Number(val) | Key::Date(val) if val.is_nan() => {
+                return Err(Error::DataError("NaN is not a valid key".into()))
+            }
+            Key::Number(val) => JsValue::from_f64(*val),
+            Key::Date(millis) => js_sys::Date::new(&JsValue::from_f64(*millis)).into(),
+            Key::String(val) => JsValue::from_str(val),
+            Key::Binary(val) => js_sys::Uint8Array::from(&val[..]).buffer().into(),
+            Key::Array(keys) => {
+                let arr = js_sys::Array::new();
+                for key in keys {
+                    arr.push(&JsValue::try_from(key)?);
+                }
+                arr.into()
+            }
+        })
+    }
+}
+
+impl TryFrom<JsValue> for Key {
+    type Error = Error;
+
+    fn try_from(val: JsValue) -> Result<Key, Error> {
+        if let Some(num) = val.as_f64() {
+            return Key::number(num)
+                .ok_or_else(|| Error::DataError("NaN is not a valid key".into()));
+        }
+        if let Some(s) = val.as_string() {
+            return Ok(Key::String(s));
+        }
+        if js_sys::Array::is_array(&val) {
+            let arr: js_sys::Array = val.unchecked_into();
+            let mut keys = Vec::with_capacity(arr.length() as usize);
+            for i in 0..arr.length() {
+                keys.push(Key::try_from(arr.get(i))?);
+            }
+            return Ok(Key::Array(keys));
+        }
+        if val.is_instance_of::<js_sys::Date>() {
+            let date: js_sys::Date = val.unchecked_into();
+            return Key::date(date.get_time())
+                .ok_or_else(|| Error::DataError("an invalid date is not a valid key".into()));
+        }
+        if val.is_instance_of::<js_sys::ArrayBuffer>() {
+            return Ok(Key::Binary(js_sys::Uint8Array::new(&val).to_vec()));
+        }
+        if js_sys::ArrayBuffer::is_view(&val) {
+            let buffer = js_sys::Reflect::get(&val, &"buffer".into())?;
+            let offset = js_sys::Reflect::get(&val, &"byteOffset".into())?;
+            let length = js_sys::Reflect::get(&val, &"byteLength".into())?;
+            let bytes = js_sys::Uint8Array::new_with_byte_offset_and_length(
+                &buffer,
+                offset.as_f64().unwrap_or(0.0) as u32,
+                length.as_f64().unwrap_or(0.0) as u32,
+            );
+            return Ok(Key::Binary(bytes.to_vec()));
+        }
+        Err(Error::DataError(format!("{:?} is not a valid key", val)))
+    }
+}
+
+#[test]
+fn test_number() {
+    assert_eq!(Key::number(1.5), Some(Key::Number(1.5)));
+    assert_eq!(Key::number(f64::NAN), None);
+    assert_eq!(Key::date(f64::NAN), None);
+    assert_eq!(Key::from(7u32), Key::Number(7.0));
+    assert_eq!(Key::try_from(1.5f32).unwrap(), Key::Number(1.5));
+    match Key::try_from(f64::NAN) {
+        Err(Error::DataError(_)) => (),
+        res => panic!("expected a DataError, got {:?}", res),
+    }
+    match JsValue::try_from(Key::Number(f64::NAN)) {
+        Err(Error::DataError(_)) => (),
+        res => panic!("expected a DataError, got {:?}", res),
+    }
+}
+
+#[test]
+fn test_from_system_time() {
+    use std::time::Duration;
+    let time = UNIX_EPOCH + Duration::from_millis(1500);
+    assert_eq!(Key::from(time), Key::Date(1500.0));
+    let time = UNIX_EPOCH - Duration::from_millis(1500);
+    assert_eq!(Key::from(time), Key::Date(-1500.0));
+}
+
+#[test]
+fn test_ord() {
+    let ordered = vec![
+        Key::Number(f64::NEG_INFINITY),
+        Key::Number(-1.0),
+        Key::Number(0.0),
+        Key::Number(f64::INFINITY),
+        Key::Number(f64::NAN),
+        Key::Date(-1.0),
+        Key::Date(5.0),
+        Key::Date(f64::NAN),
+        Key::String("".into()),
+        Key::String("a".into()),
+        Key::String("ab".into()),
+        // Outside the BMP, so encoded as a surrogate pair that sorts before U+FF61 in UTF-16.
+        Key::String("\u{1f600}".into()),
+        Key::String("\u{ff61}".into()),
+        Key::Binary(vec![]),
+        Key::Binary(vec![0]),
+        Key::Binary(vec![0, 0]),
+        Key::Binary(vec![1]),
+        Key::Array(vec![]),
+        Key::Array(vec![Key::Number(1.0)]),
+        Key::Array(vec![Key::Number(1.0), Key::Number(0.0)]),
+        Key::Array(vec![Key::String("a".into())]),
+        Key::Array(vec![Key::Array(vec![])]),
+    ];
+    for (i, a) in ordered.iter().enumerate() {
+        for (j, b) in ordered.iter().enumerate() {
+            assert_eq!(a.cmp(b), i.cmp(&j), "comparing {:?} with {:?}", a, b);
+        }
+    }
+    assert_eq!(Key::Number(0.0), Key::Number(-0.0));
+}
@@ -0,0 +1,269 @@
+use std::convert::TryFrom;
+use std::ops::{Bound, Range, RangeFrom, RangeFull, RangeInclusive, RangeTo, RangeToInclusive};
+use wasm_bindgen::JsValue;
+
+use crate::error::Error;
+use crate::key::Key;
+
+/// A continuous range of keys, mirroring `IDBKeyRange`.
+///
+/// Ranges can also be built from rust range syntax, e.g. `KeyRange::from(10..20)`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    lower: Bound<Key>,
+    upper: Bound<Key>,
+}
+
+impl KeyRange {
+    /// A range containing only the given key.
+    pub fn only(key: impl Into<Key>) -> KeyRange {
+        let key = key.into();
+        KeyRange {
+            lower: Bound::Included(key.clone()),
+            upper: Bound::Included(key),
+        }
+    }
+
+    /// A range of all keys above `lower` (or equal to it, unless `open`).
+    pub fn lower_bound(lower: impl Into<Key>, open: bool) -> KeyRange {
+        KeyRange {
+            lower: bound(lower.into(), open),
+            upper: Bound::Unbounded,
+        }
+    }
+
+    /// A range of all keys below `upper` (or equal to it, unless `open`).
+    pub fn upper_bound(upper: impl Into<Key>, open: bool) -> KeyRange {
+        KeyRange {
+            lower: Bound::Unbounded,
+            upper: bound(upper.into(), open),
+        }
+    }
+
+    /// A range of all keys between `lower` and `upper`, where each end is included unless it is
+    /// open.
+    pub fn bound(
+        lower: impl Into<Key>,
+        upper: impl Into<Key>,
+        lower_open: bool,
+        upper_open: bool,
+    ) -> KeyRange {
+        KeyRange {
+            lower: bound(lower.into(), lower_open),
+            upper: bound(upper.into(), upper_open),
+        }
+    }
+
+    /// The lower end of the range.
+    pub fn lower(&self) -> Bound<&Key> {
+        as_ref(&self.lower)
+    }
+
+    /// The upper end of the range.
+    pub fn upper(&self) -> Bound<&Key> {
+        as_ref(&self.upper)
+    }
+
+    /// Whether the given key is in the range.
+    pub fn includes(&self, key: impl Into<Key>) -> bool {
+        let key = key.into();
+        let above_lower = match &self.lower {
+            Bound::Included(lower) => &key >= lower,
+            Bound::Excluded(lower) => &key > lower,
+            Bound::Unbounded => true,
+        };
+        let below_upper = match &self.upper {
+            Bound::Included(upper) => &key <= upper,
+            Bound::Excluded(upper) => &key < upper,
+            Bound::Unbounded => true,
+        };
+        above_lower && below_upper
+    }
+
+    /// Convert the range into an `IDBKeyRange`, or `None` if it is unbounded at both ends.
+    ///
+    /// Fails if the lower end is above the upper end.
+    pub(crate) fn to_raw(&self) -> Result<Option<web_sys::IdbKeyRange>, Error> {
+        use web_sys::IdbKeyRange;
+        let range = match (&self.lower, &self.upper) {
+            (Bound::Unbounded, Bound::Unbounded) => return Ok(None),
+            (lower, Bound::Unbounded) => {
+                let (lower, open) = split(lower);
+                IdbKeyRange::lower_bound_with_open(&JsValue::try_from(lower)?, open)?
+            }
+            (Bound::Unbounded, upper) => {
+                let (upper, open) = split(upper);
+                IdbKeyRange::upper_bound_with_open(&JsValue::try_from(upper)?, open)?
+            }
+            (lower, upper) => {
+                let (lower, lower_open) = split(lower);
+                let (upper, upper_open) = split(upper);
+                IdbKeyRange::bound_with_lower_open_and_upper_open(
+                    &JsValue::try_from(lower)?,
+                    &JsValue::try_from(upper)?,
+                    lower_open,
+                    upper_open,
+                )?
+            }
+        };
+        Ok(Some(range))
+    }
+}
+
+impl<'a> TryFrom<&'a KeyRange> for web_sys::IdbKeyRange {
+    type Error = Error;
+
+    /// Fails with a `DataError` if the range is unbounded at both ends, which `IDBKeyRange` can't
+    /// express, or if the lower end is above the upper end.
+    fn try_from(range: &'a KeyRange) -> Result<web_sys::IdbKeyRange, Error> {
+        range.to_raw()?.ok_or_else(|| {
+            Error::DataError("an IDBKeyRange must be bounded at one end at least".into())
+        })
+    }
+}
+
+fn bound(key: Key, open: bool) -> Bound<Key> {
+    if open {
+        Bound::Excluded(key)
+    } else {
+        Bound::Included(key)
+    }
+}
+
+fn as_ref(bound: &Bound<Key>) -> Bound<&Key> {
+    match bound {
+        Bound::Included(key) => Bound::Included(key),
+        Bound::Excluded(key) => Bound::Excluded(key),
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
+/// Split a bounded end into its key and whether it is open.
+fn split(bound: &Bound<Key>) -> (&Key, bool) {
+    match bound {
+        Bound::Included(key) => (key, false),
+        Bound::Excluded(key) => (key, true),
+        Bound::Unbounded => unreachable!("unbounded ends are handled separately"),
+    }
+}
+
+impl<T: Into<Key>> From<Range<T>> for KeyRange {
+    fn from(range: Range<T>) -> KeyRange {
+        KeyRange::bound(range.start, range.end, false, true)
+    }
+}
+
+impl<T: Into<Key>> From<RangeInclusive<T>> for KeyRange {
+    fn from(range: RangeInclusive<T>) -> KeyRange {
+        let (start, end) = range.into_inner();
+        KeyRange::bound(start, end, false, false)
+    }
+}
+
+impl<T: Into<Key>> From<RangeFrom<T>> for KeyRange {
+    fn from(range: RangeFrom<T>) -> KeyRange {
+        KeyRange::lower_bound(range.start, false)
+    }
+}
+
+impl<T: Into<Key>> From<RangeTo<T>> for KeyRange {
+    fn from(range: RangeTo<T>) -> KeyRange {
+        KeyRange::upper_bound(range.end, true)
+    }
+}
+
+impl<T: Into<Key>> From<RangeToInclusive<T>> for KeyRange {
+    fn from(range: RangeToInclusive<T>) -> KeyRange {
+        KeyRange::upper_bound(range.end, false)
+    }
+}
+
+impl From<RangeFull> for KeyRange {
+    fn from(_: RangeFull) -> KeyRange {
+        KeyRange {
+            lower: Bound::Unbounded,
+            upper: Bound::Unbounded,
+        }
+    }
+}
+
+/// Which records a read should match: a single key, a range of keys, or everything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Query {
+    /// Match every record.
+    All,
+    /// Match the record(s) with this key.
+    Key(Key),
+    /// Match records with keys in this range.
+    Range(KeyRange),
+}
+
+impl Query {
+    /// Convert the query into the value the `IDBObjectStore` and `IDBIndex` methods expect.
+    pub(crate) fn to_js(&self) -> Result<JsValue, Error> {
+        Ok(match self {
+            Query::All => JsValue::UNDEFINED,
+            Query::Key(key) => JsValue::try_from(key)?,
+            Query::Range(range) => match range.to_raw()? {
+                Some(range) => range.into(),
+                None => JsValue::UNDEFINED,
+            },
+        })
+    }
+}
+
+impl<T: Into<Key>> From<T> for Query {
+    fn from(key: T) -> Query {
+        Query::Key(key.into())
+    }
+}
+
+impl From<KeyRange> for Query {
+    fn from(range: KeyRange) -> Query {
+        Query::Range(range)
+    }
+}
+
+macro_rules! query_from_range {
+    ($($range:ident),*) => {
+        $(
+            impl<T: Into<Key>> From<$range<T>> for Query {
+                fn from(range: $range<T>) -> Query {
+                    Query::Range(range.into())
+                }
+            }
+        )*
+    };
+}
+
+query_from_range!(Range, RangeInclusive, RangeFrom, RangeTo, RangeToInclusive);
+
+impl From<RangeFull> for Query {
+    fn from(_: RangeFull) -> Query {
+        Query::All
+    }
+}
+
+#[test]
+fn test_from_range() {
+    assert_eq!(KeyRange::from(10..20), KeyRange::bound(10, 20, false, true));
+    assert_eq!(KeyRange::from(10..=20), KeyRange::bound(10, 20, false, false));
+    assert_eq!(KeyRange::from(10..), KeyRange::lower_bound(10, false));
+    assert_eq!(KeyRange::from(..20), KeyRange::upper_bound(20, true));
+    assert_eq!(KeyRange::from(..=20), KeyRange::upper_bound(20, false));
+    assert_eq!(KeyRange::only("a").lower(), Bound::Included(&Key::from("a")));
+    assert_eq!(Query::from(..), Query::All);
+    assert_eq!(Query::from(3), Query::Key(Key::Number(3.0)));
+}
+
+#[test]
+fn test_includes() {
+    assert!(KeyRange::from(1..5).includes(1));
+    assert!(!KeyRange::from(1..5).includes(5));
+    assert!(KeyRange::from(1..=5).includes(5));
+    assert!(KeyRange::from(..).includes("anything"));
+    assert!(!KeyRange::lower_bound("a", true).includes("a"));
+    assert!(KeyRange::lower_bound("a", true).includes("b"));
+    // Strings sort after numbers.
+    assert!(!KeyRange::upper_bound(100, false).includes("1"));
+}
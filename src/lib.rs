@@ -1,66 +1,305 @@
 #[macro_use]
 mod macros;
+mod cursor;
 mod db;
+mod error;
+mod factory;
 mod index;
+mod key;
+mod key_range;
+mod migrations;
 mod object_store;
+mod request;
 mod utils;
 mod transaction;
 
+pub use crate::cursor::*;
 pub use crate::db::*;
+pub use crate::error::*;
+pub use crate::factory::*;
 pub use crate::index::*;
+pub use crate::key::*;
+pub use crate::key_range::*;
+pub use crate::migrations::*;
 pub use crate::object_store::*;
+pub use crate::request::*;
 pub use crate::transaction::*;
-use futures::{
-    future::{self, Either},
-    task, Async, Future, Poll,
-};
-use std::fmt;
-use std::sync::Arc;
-use wasm_bindgen::{closure::Closure, JsCast, JsValue};
 
-#[inline]
-fn factory() -> web_sys::IdbFactory {
-    web_sys::window().unwrap().indexed_db().unwrap().unwrap()
+/// Adapters for using this crate from code written against futures 0.1.
+///
+/// `TryFutureExt::compat` turns any of the crate's futures into a futures 0.1 future, and
+/// `TryStreamExt::compat` does the same for cursors.
+#[cfg(feature = "compat-futures01")]
+pub mod compat {
+    pub use futures::compat::Compat;
+    pub use futures::{TryFutureExt, TryStreamExt};
 }
 
+use futures::future::LocalBoxFuture;
+use std::cell::RefCell;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{self, AtomicBool};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use wasm_bindgen::{closure::Closure, JsCast};
+
 //const MAX_SAFE_INTEGER: u64 = 9007199254740991; // 2 ^ 53
 
 /// Open a database.
 ///
-/// # Panics
-///
-/// This function will panic if the new version is 0.
+/// The version must be at least 1. If the upgrade can't be started, it is aborted and the future
+/// fails with the reason.
 pub fn open(
     name: &str,
     version: u32,
     on_upgrade_needed: impl Fn(u32, DbDuringUpgrade) + 'static,
-) -> impl Future<Item = Db, Error = JsValue> {
+) -> OpenDb {
+    open_with(name, version, move |old_version, db, _| {
+        on_upgrade_needed(old_version, db)
+    })
+}
+
+/// Open a database, running a migration future during the upgrade.
+///
+/// The migration is usually an `async` block that owns the handle on the database, e.g.
+/// `Box::pin(async move { .. })`. It is driven to completion before the database is opened. If it
+/// fails, the upgrade is aborted and the open fails with its error.
+///
+/// The upgrade transaction would normally commit as soon as it has no outstanding requests, so
+/// while the migration is pending it is kept alive with a stream of cheap requests against a
+/// temporary object store, which is deleted again before the upgrade finishes (it shows up in
+/// `object_store_names` until then). The migration is only ever polled from inside the
+/// transaction's callbacks, so it can make requests even after waiting on something else, like a
+/// timer or a network fetch.
+pub fn open_with_async_upgrade<F>(
+    name: &str,
+    version: u32,
+    on_upgrade_needed: F,
+) -> OpenDb
+where
+    F: FnOnce(u32, Rc<DbDuringUpgrade>) -> LocalBoxFuture<'static, Result<(), Error>> + 'static,
+{
+    let mut on_upgrade_needed = Some(on_upgrade_needed);
+    open_with(name, version, move |old_version, db, upgrade| {
+        let on_upgrade_needed = match on_upgrade_needed.take() {
+            Some(f) => f,
+            None => return,
+        };
+        let db = Rc::new(db);
+        let future = on_upgrade_needed(old_version, db.clone());
+        let transaction = match db.raw_transaction() {
+            Ok(transaction) => transaction,
+            Err(e) => return upgrade.fail(e),
+        };
+        let keep_alive = match db.inner.create_object_store(KEEP_ALIVE_STORE) {
+            Ok(store) => store,
+            Err(e) => return upgrade.fail(e.into()),
+        };
+        Migration {
+            future,
+            db,
+            transaction,
+            keep_alive,
+            woken: Arc::new(Woken(AtomicBool::new(true))),
+            upgrade: upgrade.clone(),
+        }
+        .step();
+    })
+}
+
+fn open_with(
+    name: &str,
+    version: u32,
+    on_upgrade_needed: impl FnMut(u32, DbDuringUpgrade, &Upgrade) + 'static,
+) -> OpenDb {
     if version == 0 {
-        panic!("indexeddb version must be >= 1");
+        return OpenDb::failed(Error::type_error("indexeddb version must be >= 1"));
     }
-    let mut request = match IdbOpenDbRequest::open(name, version) {
-        Ok(request) => request,
-        Err(e) => return Either::B(future::err(e)),
+    // Can error because of origin rules.
+    let request = Factory::get().and_then(|factory| {
+        Ok(factory.inner.open_with_f64(name, version as f64)?)
+    });
+    open_request(request, on_upgrade_needed)
+}
+
+/// Wrap a raw open request, calling `on_upgrade_needed` if it starts an upgrade.
+fn open_request(
+    request: Result<web_sys::IdbOpenDbRequest, Error>,
+    mut on_upgrade_needed: impl FnMut(u32, DbDuringUpgrade, &Upgrade) + 'static,
+) -> OpenDb {
+    let mut request = match request {
+        Ok(request) => IdbOpenDbRequest::new(request),
+        Err(e) => return OpenDb::failed(e),
     };
-    let request_copy = request.inner.clone();
+    let upgrade = request.upgrade.clone();
     let onupgradeneeded = move |event: web_sys::IdbVersionChangeEvent| {
-        let old_version = cast_version(event.old_version());
-        let result = match request_copy.result() {
-            Ok(r) => r,
-            Err(e) => panic!("Error before ugradeneeded: {:?}", e),
-        };
-        on_upgrade_needed(
-            old_version,
-            DbDuringUpgrade::from_raw_unchecked(result, request_copy.clone()),
-        );
+        let started = cast_version(event.old_version()).and_then(|old_version| {
+            let result = upgrade.request.result()?;
+            Ok((old_version, result))
+        });
+        match started {
+            Ok((old_version, result)) => on_upgrade_needed(
+                old_version,
+                DbDuringUpgrade::from_raw_unchecked(result, upgrade.request.clone()),
+                &upgrade,
+            ),
+            Err(e) => upgrade.fail(e),
+        }
     };
     let onupgradeneeded =
         Closure::wrap(Box::new(onupgradeneeded) as Box<dyn FnMut(web_sys::IdbVersionChangeEvent)>);
     request
         .inner
-        .set_onupgradeneeded(Some(&onupgradeneeded.as_ref().unchecked_ref()));
+        .set_onupgradeneeded(Some(onupgradeneeded.as_ref().unchecked_ref()));
     request.onupgradeneeded.replace(onupgradeneeded);
-    Either::A(request)
+    OpenDb {
+        request: Ok(request),
+    }
+}
+
+/// A future that opens a database, returned by `open` and its variants.
+///
+/// If the database needs upgrading while other connections to it are still open (e.g. in another
+/// tab), the open waits until they have all closed. Use `on_blocked` to find out when this
+/// happens.
+#[derive(Debug)]
+pub struct OpenDb {
+    // If the open could not be started, the error is reported on the first poll.
+    request: Result<IdbOpenDbRequest, Option<Error>>,
+}
+
+impl OpenDb {
+    pub(crate) fn failed(error: Error) -> Self {
+        OpenDb {
+            request: Err(Some(error)),
+        }
+    }
+
+    /// Call `on_blocked` if the open has to wait for other connections to the database to close.
+    ///
+    /// Connections opened by this crate close themselves by default when they are in the way of
+    /// an upgrade (see `Db::set_close_on_version_change`), so this usually means another
+    /// connection is refusing to close.
+    pub fn on_blocked(mut self, mut on_blocked: impl FnMut(VersionChange) + 'static) -> Self {
+        if let Ok(ref mut request) = self.request {
+            let onblocked = Closure::wrap(Box::new(move |event: web_sys::IdbVersionChangeEvent| {
+                on_blocked(VersionChange::from(&event))
+            }) as Box<dyn FnMut(web_sys::IdbVersionChangeEvent)>);
+            request
+                .inner
+                .set_onblocked(Some(onblocked.as_ref().unchecked_ref()));
+            request.onblocked.replace(onblocked);
+        }
+        self
+    }
+}
+
+impl Future for OpenDb {
+    type Output = Result<Db, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        match self.get_mut().request {
+            Ok(ref mut request) => Pin::new(request).poll(cx),
+            Err(ref mut error) => Poll::Ready(Err(error
+                .take()
+                .expect("cannot poll OpenDb after it has failed"))),
+        }
+    }
+}
+
+/// The state of an upgrade, shared between the open request and the upgrade callback.
+#[derive(Clone)]
+struct Upgrade {
+    request: Arc<web_sys::IdbOpenDbRequest>,
+    // Set if we had to abort the upgrade ourselves.
+    error: Rc<RefCell<Option<Error>>>,
+}
+
+impl Upgrade {
+    /// Abort the upgrade, and report `error` in place of the abort error.
+    fn fail(&self, error: Error) {
+        self.error.borrow_mut().replace(error);
+        if let Some(transaction) = self.request.transaction() {
+            let _ = transaction.abort();
+        }
+    }
+}
+
+/// The temporary object store used to keep the upgrade transaction alive during a migration.
+const KEEP_ALIVE_STORE: &str = "__indexeddb_migration_keep_alive";
+
+/// A migration future, driven from inside the upgrade transaction's callbacks.
+struct Migration {
+    future: LocalBoxFuture<'static, Result<(), Error>>,
+    db: Rc<DbDuringUpgrade>,
+    transaction: web_sys::IdbTransaction,
+    keep_alive: web_sys::IdbObjectStore,
+    woken: Arc<Woken>,
+    upgrade: Upgrade,
+}
+
+impl Migration {
+    /// Poll the migration if it has been woken, then either finish the upgrade or make another
+    /// request to keep the transaction alive until the next step.
+    ///
+    /// This must only be called while the upgrade transaction is active.
+    fn step(mut self) {
+        if self.woken.0.swap(false, atomic::Ordering::SeqCst) {
+            let waker = Waker::from(self.woken.clone());
+            match self.future.as_mut().poll(&mut Context::from_waker(&waker)) {
+                Poll::Ready(Ok(())) => {
+                    if let Err(e) = self.db.delete_object_store(KEEP_ALIVE_STORE) {
+                        self.upgrade.fail(e);
+                    }
+                    return;
+                }
+                Poll::Ready(Err(e)) => return self.upgrade.fail(e),
+                Poll::Pending => (),
+            }
+        }
+        let request = match self.keep_alive.count() {
+            Ok(request) => request,
+            Err(e) => return self.upgrade.fail(e.into()),
+        };
+        let raw = request.clone();
+        // Called once, whichever way the request ends, and freed afterwards.
+        let next = Closure::once_into_js(move || match raw.error() {
+            Ok(None) => self.step(),
+            // The request only fails if the upgrade has been aborted, so the migration is dropped.
+            _ => self.aborted(),
+        });
+        request.set_onsuccess(Some(next.unchecked_ref()));
+        request.set_onerror(Some(next.unchecked_ref()));
+    }
+
+    /// Report why the upgrade was aborted, unless we aborted it ourselves.
+    ///
+    /// A request in the migration that fails aborts the upgrade as its error bubbles up, and the
+    /// open should fail with that error rather than a generic abort.
+    fn aborted(self) {
+        let mut error = self.upgrade.error.borrow_mut();
+        if error.is_none() {
+            if let Some(e) = self.transaction.error() {
+                error.replace(e.into());
+            }
+        }
+    }
+}
+
+/// Records that a migration has been woken, so it is polled on the next step.
+struct Woken(AtomicBool);
+
+impl Wake for Woken {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.0.store(true, atomic::Ordering::SeqCst);
+    }
 }
 
 /// Wraps the open db request. Private - the user interacts with the request using the function
@@ -71,18 +310,24 @@ struct IdbOpenDbRequest {
     onsuccess: Option<Closure<dyn FnMut()>>,
     onerror: Option<Closure<dyn FnMut()>>,
     onupgradeneeded: Option<Closure<dyn FnMut(web_sys::IdbVersionChangeEvent)>>,
+    onblocked: Option<Closure<dyn FnMut(web_sys::IdbVersionChangeEvent)>>,
+    upgrade: Upgrade,
 }
 
 impl IdbOpenDbRequest {
-    fn open(name: &str, version: u32) -> Result<IdbOpenDbRequest, JsValue> {
-        // Can error because of origin rules.
-        let inner = factory().open_with_f64(name, version as f64)?;
-        Ok(IdbOpenDbRequest {
-            inner: Arc::new(inner),
+    fn new(inner: web_sys::IdbOpenDbRequest) -> IdbOpenDbRequest {
+        let inner = Arc::new(inner);
+        IdbOpenDbRequest {
+            inner: inner.clone(),
             onsuccess: None,
             onerror: None,
             onupgradeneeded: None,
-        })
+            onblocked: None,
+            upgrade: Upgrade {
+                request: inner,
+                error: Rc::new(RefCell::new(None)),
+            },
+        }
     }
 }
 
@@ -92,44 +337,81 @@ impl fmt::Debug for IdbOpenDbRequest {
     }
 }
 
+impl Drop for IdbOpenDbRequest {
+    fn drop(&mut self) {
+        self.inner.set_onblocked(None);
+        if let Some(transaction) = self.inner.transaction() {
+            // Dropped part way through an upgrade, so give up on it. Aborting also closes the
+            // connection.
+            let _ = transaction.abort();
+        } else if self.inner.ready_state() == web_sys::IdbRequestReadyState::Pending {
+            // The open still goes ahead once it is unblocked, and would otherwise upgrade the
+            // database without running the upgrade, then leak the connection it opens.
+            let request = (*self.inner).clone();
+            let cancel = Closure::once_into_js(move || {
+                request.set_onsuccess(None);
+                request.set_onerror(None);
+                request.set_onupgradeneeded(None);
+                if let Some(transaction) = request.transaction() {
+                    let _ = transaction.abort();
+                } else if let Ok(db) = request.result() {
+                    db.unchecked_into::<web_sys::IdbDatabase>().close();
+                }
+            });
+            self.inner.set_onsuccess(Some(cancel.unchecked_ref()));
+            self.inner.set_onerror(Some(cancel.unchecked_ref()));
+            self.inner
+                .set_onupgradeneeded(Some(cancel.unchecked_ref()));
+            return;
+        }
+        // Don't leave dangling callbacks on the request.
+        self.inner.set_onsuccess(None);
+        self.inner.set_onerror(None);
+        self.inner.set_onupgradeneeded(None);
+    }
+}
+
 impl Future for IdbOpenDbRequest {
-    type Item = Db;
-    type Error = JsValue;
+    type Output = Result<Db, Error>;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         use web_sys::IdbRequestReadyState as ReadyState;
-        match self.inner.ready_state() {
+        let this = self.get_mut();
+        match this.inner.ready_state() {
             ReadyState::Pending => {
-                let success_notifier = task::current();
-                let error_notifier = success_notifier.clone();
-                // If we're not ready set up onsuccess and onerror callbacks to notify the
+                let success_waker = cx.waker().clone();
+                let error_waker = success_waker.clone();
+                // If we're not ready set up onsuccess and onerror callbacks to wake the
                 // executor.
                 let onsuccess = Closure::wrap(Box::new(move || {
-                    success_notifier.notify();
-                }) as Box<FnMut()>);
-                self.inner
+                    success_waker.wake_by_ref();
+                }) as Box<dyn FnMut()>);
+                this.inner
                     .set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
-                self.onsuccess.replace(onsuccess); // drop the old closure if there was one
+                this.onsuccess.replace(onsuccess); // drop the old closure if there was one
 
                 let onerror = Closure::wrap(Box::new(move || {
-                    error_notifier.notify();
-                }) as Box<FnMut()>);
-                self.inner
-                    .set_onerror(Some(&onerror.as_ref().unchecked_ref()));
-                self.onerror.replace(onerror); // drop the old closure if there was one
+                    error_waker.wake_by_ref();
+                }) as Box<dyn FnMut()>);
+                this.inner
+                    .set_onerror(Some(onerror.as_ref().unchecked_ref()));
+                this.onerror.replace(onerror); // drop the old closure if there was one
 
-                Ok(Async::NotReady)
+                Poll::Pending
             }
-            ReadyState::Done => match self.inner.result() {
-                Ok(val) => Ok(Async::Ready(Db {
-                    inner: val.unchecked_into(),
-                })),
-                Err(_) => match self.inner.error() {
-                    Ok(Some(e)) => Err(e.into()),
-                    Ok(None) => unreachable!("internal error polling open db request"),
-                    Err(e) => Err(e),
-                },
-            },
+            ReadyState::Done => Poll::Ready(match this.inner.result() {
+                Ok(val) => Ok(Db::new(val.unchecked_into())),
+                Err(_) => {
+                    if let Some(e) = this.upgrade.error.borrow_mut().take() {
+                        return Poll::Ready(Err(e));
+                    }
+                    match this.inner.error() {
+                        Ok(Some(e)) => Err(e.into()),
+                        Ok(None) => unreachable!("internal error polling open db request"),
+                        Err(e) => Err(e.into()),
+                    }
+                }
+            }),
             _ => panic!("unexpected ready state"),
         }
     }
@@ -137,22 +419,25 @@ impl Future for IdbOpenDbRequest {
 
 // Some u64 numbers cannot be represented as f64. This checks as part of the cast.
 // https://stackoverflow.com/questions/3793838/which-is-the-first-integer-that-an-ieee-754-float-is-incapable-of-representing-e
-fn cast_version(val: f64) -> u32 {
-    if val < 0.0 || val > u32::max_value() as f64 {
-        panic!("out of bounds");
+fn cast_version(val: f64) -> Result<u32, Error> {
+    if val < 0.0 || val > u32::MAX as f64 {
+        return Err(Error::type_error(format!(
+            "database version {} is out of bounds",
+            val
+        )));
     }
-    val as u32
+    Ok(val as u32)
 }
 
 #[test]
 fn test_cast() {
-    for val in vec![0u32, 1, 10] {
-        assert_eq!(cast_version(val as f64), val);
+    for val in [0u32, 1, 10] {
+        assert_eq!(cast_version(val as f64), Ok(val));
     }
 }
 
 #[test]
-#[should_panic]
-fn test_cast_too_big() {
-    cast_version((1u64 << 54) as f64);
+fn test_cast_out_of_bounds() {
+    assert!(cast_version((1u64 << 54) as f64).is_err());
+    assert!(cast_version(-1.0).is_err());
 }
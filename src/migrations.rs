@@ -0,0 +1,135 @@
+use std::fmt;
+
+use crate::db::DbDuringUpgrade;
+use crate::error::Error;
+use crate::OpenDb;
+
+/// A step that upgrades the database schema (and data) to a given version.
+type Step = Box<dyn Fn(&DbDuringUpgrade) -> Result<(), Error>>;
+
+/// A set of versioned migrations to open a database with.
+///
+/// Each step is registered against the version it upgrades the database to. When the database
+/// is opened, every step for a version above the database's current version is run in order,
+/// all inside the one upgrade transaction, and the database ends up at the highest registered
+/// version. If a step fails, the whole upgrade is rolled back and the open fails with its error.
+#[derive(Default)]
+pub struct Migrations {
+    steps: Vec<(u32, Step)>,
+    // The first step registered against version 0, counting from 1, reported when opening.
+    invalid_step: Option<usize>,
+}
+
+impl Migrations {
+    /// An empty set of migrations.
+    pub fn new() -> Self {
+        Migrations::default()
+    }
+
+    /// Register a step that upgrades the database to `version`, which must be at least 1.
+    ///
+    /// Steps for the same version run in the order they were registered.
+    pub fn version(
+        mut self,
+        version: u32,
+        step: impl Fn(&DbDuringUpgrade) -> Result<(), Error> + 'static,
+    ) -> Self {
+        if version == 0 && self.invalid_step.is_none() {
+            self.invalid_step = Some(self.steps.len() + 1);
+        }
+        self.steps.push((version, Box::new(step)));
+        // A stable sort, so steps for the same version keep their order.
+        self.steps.sort_by_key(|&(version, _)| version);
+        self
+    }
+
+    /// The version the database will be at once the migrations have run, which is the highest
+    /// registered version (or 0 if there are no steps).
+    pub fn latest_version(&self) -> u32 {
+        self.steps.last().map(|&(version, _)| version).unwrap_or(0)
+    }
+
+    /// Open the database, running the migrations it needs.
+    ///
+    /// Fails with a `TypeError` if no steps are registered, or if a step is registered against
+    /// version 0.
+    pub fn open(self, name: &str) -> OpenDb {
+        let version = match self.check() {
+            Ok(version) => version,
+            Err(e) => return OpenDb::failed(e),
+        };
+        crate::open_with(name, version, move |old_version, db, upgrade| {
+            for (_, step) in self.pending(old_version) {
+                if let Err(e) = step(&db) {
+                    upgrade.fail(e);
+                    return;
+                }
+            }
+        })
+    }
+
+    /// Check the steps can be run, returning the version to open the database at.
+    fn check(&self) -> Result<u32, Error> {
+        if let Some(step) = self.invalid_step {
+            return Err(Error::type_error(format!(
+                "migration step {} is registered against version 0, but versions start at 1",
+                step
+            )));
+        }
+        match self.latest_version() {
+            0 => Err(Error::type_error("no migration steps are registered")),
+            version => Ok(version),
+        }
+    }
+
+    /// The steps to run to upgrade a database at `old_version`.
+    fn pending(&self, old_version: u32) -> impl Iterator<Item = &(u32, Step)> {
+        self.steps
+            .iter()
+            .filter(move |&&(version, _)| version > old_version)
+    }
+}
+
+impl fmt::Debug for Migrations {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let versions: Vec<u32> = self.steps.iter().map(|&(version, _)| version).collect();
+        f.debug_struct("Migrations")
+            .field("versions", &versions)
+            .finish()
+    }
+}
+
+#[test]
+fn test_pending() {
+    let migrations = Migrations::new()
+        .version(3, |_| Ok(()))
+        .version(1, |_| Ok(()))
+        .version(2, |_| Ok(()))
+        .version(2, |_| Ok(()));
+    assert_eq!(migrations.latest_version(), 3);
+    let pending = |old_version| -> Vec<u32> {
+        migrations
+            .pending(old_version)
+            .map(|&(version, _)| version)
+            .collect()
+    };
+    assert_eq!(pending(0), vec![1, 2, 2, 3]);
+    assert_eq!(pending(1), vec![2, 2, 3]);
+    assert_eq!(pending(3), Vec::<u32>::new());
+    assert_eq!(Migrations::new().latest_version(), 0);
+}
+
+#[test]
+fn test_check() {
+    let migrations = Migrations::new().version(1, |_| Ok(())).version(2, |_| Ok(()));
+    assert_eq!(migrations.check(), Ok(2));
+    let migrations = Migrations::new()
+        .version(1, |_| Ok(()))
+        .version(0, |_| Ok(()))
+        .version(0, |_| Ok(()));
+    match migrations.check() {
+        Err(Error::Unknown { ref message, .. }) => assert!(message.contains("step 2 ")),
+        res => panic!("expected a TypeError, got {:?}", res),
+    }
+    assert!(Migrations::new().check().is_err());
+}
@@ -1,10 +1,19 @@
 use crate::db::DbDuringUpgrade;
-use crate::index::{IndexDuringUpgrade, Index};
+use crate::index::{Index, IndexDuringUpgrade, IndexOptions};
+use crate::request::Request;
+use crate::cursor::{Cursor, CursorDirection};
+use crate::error::Error;
+use crate::key::Key;
+use crate::key_range::{KeyRange, Query};
+use crate::transaction::{Mode, ReadWrite, Unchecked};
+use crate::utils::{to_count, to_key, to_keys, to_option, to_option_key, to_vec};
 use std::collections::HashSet;
+use std::convert::TryFrom;
 use std::marker::PhantomData;
 use std::mem;
 use std::ops::Deref;
 use wasm_bindgen::prelude::*;
+use wasm_bindgen::JsCast;
 
 #[derive(Debug)]
 pub struct ObjectStoreDuringUpgrade<'a> {
@@ -14,19 +23,37 @@ pub struct ObjectStoreDuringUpgrade<'a> {
 
 impl<'a> ObjectStoreDuringUpgrade<'a> {
     /// Delete this object store.
-    pub fn delete(self) -> Result<(), JsValue> {
+    pub fn delete(self) -> Result<(), Error> {
         self.db.delete_object_store(&self.name())
     }
 
+    /// Rename this object store, keeping its records and indexes.
+    ///
+    /// Fails if another object store already has the new name.
+    pub fn rename(&self, new_name: &str) -> Result<(), Error> {
+        if new_name == self.name() {
+            return Ok(());
+        }
+        if self.db.store_exists(new_name) {
+            return Err(Error::ConstraintError(format!(
+                "an object store called \"{}\" already exists",
+                new_name
+            )));
+        }
+        self.inner.set_name(new_name);
+        Ok(())
+    }
+
+    /// Create an index on this object store. `options` can be an `IndexOptions`, or just whether
+    /// the index is unique.
     pub fn create_index(
         &'a self,
         name: &str,
         key_path: impl Into<KeyPath>,
-        unique: bool,
-    ) -> Result<IndexDuringUpgrade<'a>, JsValue> {
+        options: impl Into<IndexOptions>,
+    ) -> Result<IndexDuringUpgrade<'a>, Error> {
         let key_path: KeyPath = key_path.into();
-        let mut params = web_sys::IdbIndexParameters::new();
-        params.unique(unique);
+        let params = options.into().to_raw();
         // https://developer.mozilla.org/en-US/docs/Web/API/IDBObjectStore/createIndex#Exceptions
         // we should be able to check for all error conditions at compile-time, but not yet done.
         let index = self
@@ -43,40 +70,45 @@ impl<'a> ObjectStoreDuringUpgrade<'a> {
     }
 
     /// Delete an index.
-    pub(crate) fn delete_index(&self, name: &str) -> Result<(), JsValue> {
-        self.inner.delete_index(name)
+    pub(crate) fn delete_index(&self, name: &str) -> Result<(), Error> {
+        Ok(self.inner.delete_index(name)?)
     }
 
     /// Get an already-existing index.
-    pub fn index(&'a self, name: &str) -> Result<IndexDuringUpgrade<'a>, JsValue> {
-        self.inner.index(name).map(|inner| IndexDuringUpgrade::new(inner, self))
+    pub fn index(&'a self, name: &str) -> Result<IndexDuringUpgrade<'a>, Error> {
+        let inner = self.inner.index(name)?;
+        Ok(IndexDuringUpgrade::new(inner, self))
     }
 }
 
 impl<'a> Deref for ObjectStoreDuringUpgrade<'a> {
-    type Target = ObjectStore<'a>;
+    type Target = ObjectStore<'a, ReadWrite>;
 
     fn deref(&self) -> &Self::Target {
         unsafe { mem::transmute(&self.inner) }
     }
 }
 
+/// An object store (roughly equivalent to a table).
+///
+/// `M` is what the store's transaction is statically known to allow (see `Mode`).
 #[repr(transparent)]
 #[derive(Debug)]
-pub struct ObjectStore<'a> {
+pub struct ObjectStore<'a, M: Mode = Unchecked> {
     pub(crate) inner: web_sys::IdbObjectStore,
     pub(crate) db: PhantomData<&'a ()>,
+    pub(crate) mode: PhantomData<M>,
 }
 
-impl<'a> ObjectStore<'a> {
+impl<'a, M: Mode> ObjectStore<'a, M> {
     /// The name of the object store.
     pub fn name(&self) -> String {
         self.inner.name()
     }
 
     /// The key path of the object store. No key path means keys are stored out-of-tree.
-    pub fn key_path(&self) -> KeyPath {
-        self.inner.key_path().unwrap().into()
+    pub fn key_path(&self) -> Result<KeyPath, Error> {
+        KeyPath::try_from(self.inner.key_path()?)
     }
 
     /// Whether they primary key uses an auto-generated incrementing number as its value.
@@ -90,8 +122,204 @@ impl<'a> ObjectStore<'a> {
     }
 
     /// Get an index.
-    pub fn index(&'a self, name: &'_ str) -> Result<Index<'a>, JsValue> {
-        self.inner.index(name).map(|inner| Index::new(inner, self))
+    pub fn index(&'a self, name: &'_ str) -> Result<Index<'a, M>, Error> {
+        let inner = self.inner.index(name)?;
+        Ok(Index::new(inner, self))
+    }
+
+    /// Get the value of the first record matching the query (a key or key range).
+    pub fn get(&self, query: impl Into<Query>) -> Request<'a, Option<JsValue>> {
+        let request = query
+            .into()
+            .to_js()
+            .and_then(|query| Ok(self.inner.get(&query)?));
+        Request::new(request, to_option)
+    }
+
+    /// Get the key of the first record matching the query (a key or key range).
+    pub fn get_key(&self, query: impl Into<Query>) -> Request<'a, Option<Key>> {
+        let request = query
+            .into()
+            .to_js()
+            .and_then(|query| Ok(self.inner.get_key(&query)?));
+        Request::new(request, to_option_key)
+    }
+
+    /// Get the values of all records matching the query, up to `count` of them.
+    pub fn get_all(
+        &self,
+        query: impl Into<Query>,
+        count: Option<u32>,
+    ) -> Request<'a, Vec<JsValue>> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(match count {
+                Some(count) => self.inner.get_all_with_key_and_limit(&query, count)?,
+                None => self.inner.get_all_with_key(&query)?,
+            })
+        });
+        Request::new(request, to_vec)
+    }
+
+    /// Get the keys of all records matching the query, up to `count` of them.
+    pub fn get_all_keys(
+        &self,
+        query: impl Into<Query>,
+        count: Option<u32>,
+    ) -> Request<'a, Vec<Key>> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(match count {
+                Some(count) => self.inner.get_all_keys_with_key_and_limit(&query, count)?,
+                None => self.inner.get_all_keys_with_key(&query)?,
+            })
+        });
+        Request::new(request, to_keys)
+    }
+
+    /// Open a cursor over the records in the store matching the query, visiting them in the
+    /// given direction.
+    pub fn open_cursor(
+        &self,
+        query: impl Into<Query>,
+        direction: CursorDirection,
+    ) -> Cursor<'a, M> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(self
+                .inner
+                .open_cursor_with_range_and_direction(&query, direction.into())?)
+        });
+        Cursor::new(request)
+    }
+
+    /// Open a cursor over the keys of the records in the store matching the query, visiting them
+    /// in the given direction. This avoids loading the values.
+    pub fn open_key_cursor(
+        &self,
+        query: impl Into<Query>,
+        direction: CursorDirection,
+    ) -> Cursor<'a, M> {
+        let request = query.into().to_js().and_then(|query| {
+            Ok(self
+                .inner
+                .open_key_cursor_with_range_and_direction(&query, direction.into())?)
+        });
+        Cursor::new(request)
+    }
+
+    /// Insert or replace a record, returning its key.
+    ///
+    /// The store must either use in-line keys or have a key generator.
+    pub fn put(&self, value: &JsValue) -> Request<'a, Key> {
+        let request = self
+            .check_read_write()
+            .and_then(|()| self.check_generated_key())
+            .and_then(|()| Ok(self.inner.put(value)?));
+        Request::new(request, to_key)
+    }
+
+    /// Insert or replace a record under the given key, returning the key.
+    ///
+    /// The store must use out-of-line keys.
+    pub fn put_with_key(&self, value: &JsValue, key: impl Into<Key>) -> Request<'a, Key> {
+        let key = JsValue::try_from(key.into());
+        let request = self
+            .check_read_write()
+            .and_then(|()| self.check_explicit_key())
+            .and_then(|()| Ok(self.inner.put_with_key(value, &key?)?));
+        Request::new(request, to_key)
+    }
+
+    /// Insert a new record, returning its key. Fails if a record with the same key exists.
+    ///
+    /// The store must either use in-line keys or have a key generator.
+    pub fn add(&self, value: &JsValue) -> Request<'a, Key> {
+        let request = self
+            .check_read_write()
+            .and_then(|()| self.check_generated_key())
+            .and_then(|()| Ok(self.inner.add(value)?));
+        Request::new(request, to_key)
+    }
+
+    /// Insert a new record under the given key, returning the key. Fails if a record with the
+    /// same key exists.
+    ///
+    /// The store must use out-of-line keys.
+    pub fn add_with_key(&self, value: &JsValue, key: impl Into<Key>) -> Request<'a, Key> {
+        let key = JsValue::try_from(key.into());
+        let request = self
+            .check_read_write()
+            .and_then(|()| self.check_explicit_key())
+            .and_then(|()| Ok(self.inner.add_with_key(value, &key?)?));
+        Request::new(request, to_key)
+    }
+
+    /// Delete the record with the given key.
+    pub fn delete(&self, key: impl Into<Key>) -> Request<'a, ()> {
+        let key = JsValue::try_from(key.into());
+        let request = self
+            .check_read_write()
+            .and_then(|()| Ok(self.inner.delete(&key?)?));
+        Request::new(request, |_| Ok(()))
+    }
+
+    /// Delete all records with keys in the given key range.
+    pub fn delete_range(&self, range: impl Into<KeyRange>) -> Request<'a, ()> {
+        let range = Query::Range(range.into());
+        let request = self
+            .check_read_write()
+            .and_then(|()| range.to_js())
+            .and_then(|range| Ok(self.inner.delete(&range)?));
+        Request::new(request, |_| Ok(()))
+    }
+
+    /// Delete all records in the store.
+    pub fn clear(&self) -> Request<'a, ()> {
+        let request = self
+            .check_read_write()
+            .and_then(|()| Ok(self.inner.clear()?));
+        Request::new(request, |_| Ok(()))
+    }
+
+    /// Count the records matching the query.
+    pub fn count(&self, query: impl Into<Query>) -> Request<'a, u32> {
+        let request = query
+            .into()
+            .to_js()
+            .and_then(|query| Ok(self.inner.count_with_key(&query)?));
+        Request::new(request, to_count)
+    }
+
+    /// Check that the transaction this store belongs to can modify it.
+    fn check_read_write(&self) -> Result<(), Error> {
+        if self.inner.transaction().mode()? == web_sys::IdbTransactionMode::Readonly {
+            return Err(Error::ReadOnly(format!(
+                "cannot modify object store \"{}\" in a read-only transaction",
+                self.name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that a record can be stored without giving a key.
+    fn check_generated_key(&self) -> Result<(), Error> {
+        if self.key_path()? == KeyPath::None && !self.auto_increment() {
+            return Err(Error::DataError(format!(
+                "object store \"{}\" uses out-of-line keys and has no key generator, so a key \
+                 must be given",
+                self.name()
+            )));
+        }
+        Ok(())
+    }
+
+    /// Check that a record can be stored with an explicit key.
+    fn check_explicit_key(&self) -> Result<(), Error> {
+        if self.key_path()? != KeyPath::None {
+            return Err(Error::DataError(format!(
+                "object store \"{}\" uses in-line keys, so a key cannot be given",
+                self.name()
+            )));
+        }
+        Ok(())
     }
 }
 
@@ -102,9 +330,8 @@ pub enum KeyPath {
     None,
     /// The path to the single key.
     Single(String),
-    // This complains when I use it in the browser TODO investigate.
-    // /// The paths to all the parts of the key.
-    // Multi(Vec<String>),
+    /// The paths to all the parts of the key, which is an array of their values.
+    Multi(Vec<String>),
 }
 
 impl From<KeyPath> for JsValue {
@@ -112,54 +339,55 @@ impl From<KeyPath> for JsValue {
         match key_path {
             KeyPath::None => JsValue::NULL,
             KeyPath::Single(path) => JsValue::from(path),
-            //KeyPath::Multi(paths) => from_collection!(paths).into(),
+            KeyPath::Multi(paths) => from_collection!(paths).into(),
         }
     }
 }
 
-impl From<JsValue> for KeyPath {
-    fn from(val: JsValue) -> KeyPath {
+impl TryFrom<JsValue> for KeyPath {
+    type Error = Error;
+
+    fn try_from(val: JsValue) -> Result<KeyPath, Error> {
         if val.is_null() || val.is_undefined() {
-            KeyPath::None
-        } else if let Some(s) = val.as_string() {
-            KeyPath::Single(s)
-        } else {
-            panic!("expected string or null");
-            /*
-            let arr = match val.dyn_into::<js_sys::Array>() {
-                Ok(v) => v,
-                Err(e) => panic!("expected array of strings, found {:?}", e),
-            };
-            let mut out = vec![];
-            for el in arr.values().into_iter() {
-                let el = el.unwrap();
-                if let Some(val) = el.as_string() {
-                    out.push(val);
-                } else {
-                    panic!("Expected string, found {:?}", el);
+            return Ok(KeyPath::None);
+        }
+        if let Some(s) = val.as_string() {
+            return Ok(KeyPath::Single(s));
+        }
+        // Some browsers give back a `DOMStringList` rather than an array.
+        if let Some(list) = val.dyn_ref::<web_sys::DomStringList>() {
+            return Ok(KeyPath::Multi(to_collection!(list => Vec<String> : push)));
+        }
+        if js_sys::Array::is_array(&val) {
+            let arr: js_sys::Array = val.unchecked_into();
+            let mut paths = Vec::with_capacity(arr.length() as usize);
+            for i in 0..arr.length() {
+                let path = arr.get(i);
+                match path.as_string() {
+                    Some(path) => paths.push(path),
+                    None => return Err(Error::DataError(format!("{:?} is not a key path", path))),
                 }
             }
-            KeyPath::Multi(out)
-            */
+            return Ok(KeyPath::Multi(paths));
         }
+        Err(Error::DataError(format!("{:?} is not a key path", val)))
     }
 }
 
-/*
 impl From<Vec<String>> for KeyPath {
     fn from(inner: Vec<String>) -> KeyPath {
         KeyPath::Multi(inner)
     }
 }
 
-impl<S> From<&[S]> for KeyPath
-where S: AsRef<str>
+impl<'a, S> From<&'a [S]> for KeyPath
+where
+    S: AsRef<str>,
 {
-    fn from(inner: &[S]) -> KeyPath {
+    fn from(inner: &'a [S]) -> KeyPath {
         KeyPath::Multi(inner.iter().map(|s| s.as_ref().to_owned()).collect())
     }
 }
-*/
 
 impl From<String> for KeyPath {
     fn from(inner: String) -> KeyPath {
@@ -0,0 +1,104 @@
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+use crate::error::Error;
+
+/// A future wrapping an `IDBRequest`, resolving to its (converted) result.
+///
+/// Unlike most futures, a request is eager: the browser starts it as soon as it is made, and it
+/// runs to completion whether or not the future is ever polled. Dropping the future doesn't
+/// cancel the request, it only stops this side listening for the result; a failed request still
+/// aborts its transaction. To undo writes, abort the transaction instead.
+///
+/// The lifetime ties the request to the transaction it was made in.
+#[must_use = "requests run anyway, but their result and any error are lost unless awaited"]
+pub struct Request<'a, T> {
+    // If we failed before making the request, the error is reported on the first poll.
+    inner: Result<web_sys::IdbRequest, Option<Error>>,
+    map: fn(JsValue) -> Result<T, Error>,
+    onsuccess: Option<Closure<dyn FnMut()>>,
+    onerror: Option<Closure<dyn FnMut()>>,
+    transaction: PhantomData<&'a ()>,
+}
+
+impl<'a, T> Request<'a, T> {
+    pub(crate) fn new(
+        inner: Result<web_sys::IdbRequest, Error>,
+        map: fn(JsValue) -> Result<T, Error>,
+    ) -> Self {
+        Request {
+            inner: inner.map_err(Some),
+            map,
+            onsuccess: None,
+            onerror: None,
+            transaction: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> fmt::Debug for Request<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Request")
+    }
+}
+
+impl<'a, T> Future for Request<'a, T> {
+    type Output = Result<T, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        use web_sys::IdbRequestReadyState as ReadyState;
+        let this = self.get_mut();
+        let inner = match this.inner {
+            Ok(ref inner) => inner,
+            Err(ref mut e) => {
+                return Poll::Ready(Err(e.take().expect("request polled after failure")))
+            }
+        };
+        match inner.ready_state() {
+            ReadyState::Pending => {
+                let success_waker = cx.waker().clone();
+                let error_waker = success_waker.clone();
+                let onsuccess = Closure::wrap(Box::new(move || {
+                    success_waker.wake_by_ref();
+                }) as Box<dyn FnMut()>);
+                inner.set_onsuccess(Some(onsuccess.as_ref().unchecked_ref()));
+                this.onsuccess.replace(onsuccess); // drop the old closure if there was one
+
+                let onerror = Closure::wrap(Box::new(move || {
+                    error_waker.wake_by_ref();
+                }) as Box<dyn FnMut()>);
+                inner.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+                this.onerror.replace(onerror); // drop the old closure if there was one
+
+                Poll::Pending
+            }
+            ReadyState::Done => Poll::Ready(match inner.result() {
+                Ok(val) => (this.map)(val),
+                Err(_) => match inner.error() {
+                    Ok(Some(e)) => Err(e.into()),
+                    Ok(None) => unreachable!("internal error polling request"),
+                    Err(e) => Err(e.into()),
+                },
+            }),
+            _ => panic!("unexpected ready state"),
+        }
+    }
+}
+
+impl<'a, T> Drop for Request<'a, T> {
+    fn drop(&mut self) {
+        if let Ok(ref inner) = self.inner {
+            // Don't leave dangling callbacks on the request.
+            if self.onsuccess.is_some() {
+                inner.set_onsuccess(None);
+            }
+            if self.onerror.is_some() {
+                inner.set_onerror(None);
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::future::Future;
+use std::marker::PhantomData;
+use std::ops::Deref;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use wasm_bindgen::{closure::Closure, JsCast};
+
+use crate::db::{Db, DbDuringUpgrade};
+use crate::error::Error;
+use crate::object_store::{ObjectStore, ObjectStoreDuringUpgrade};
+use crate::utils::{transaction_channel, TReceiver};
+
+/// The mode of a transaction.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum TransactionMode {
+    /// The transaction can only read data.
+    ReadOnly,
+    /// The transaction can read and write data.
+    ReadWrite,
+    /// The transaction used while upgrading the database. These cannot be created directly.
+    VersionChange,
+}
+
+impl From<TransactionMode> for web_sys::IdbTransactionMode {
+    fn from(mode: TransactionMode) -> web_sys::IdbTransactionMode {
+        match mode {
+            TransactionMode::ReadOnly => web_sys::IdbTransactionMode::Readonly,
+            TransactionMode::ReadWrite => web_sys::IdbTransactionMode::Readwrite,
+            TransactionMode::VersionChange => web_sys::IdbTransactionMode::Versionchange,
+        }
+    }
+}
+
+impl TryFrom<web_sys::IdbTransactionMode> for TransactionMode {
+    type Error = Error;
+
+    /// Fails with a `TypeError` for modes this crate doesn't know about, such as the non-standard
+    /// `readwriteflush`.
+    fn try_from(mode: web_sys::IdbTransactionMode) -> Result<TransactionMode, Error> {
+        match mode {
+            web_sys::IdbTransactionMode::Readonly => Ok(TransactionMode::ReadOnly),
+            web_sys::IdbTransactionMode::Readwrite => Ok(TransactionMode::ReadWrite),
+            web_sys::IdbTransactionMode::Versionchange => Ok(TransactionMode::VersionChange),
+            mode => Err(Error::type_error(format!(
+                "unexpected transaction mode {:?}",
+                mode
+            ))),
+        }
+    }
+}
+
+/// Marks handles whose transaction's mode is only known at runtime.
+///
+/// Handles from a plain `Transaction` are marked this way whatever mode it was opened in. They
+/// can still write through the object store, failing with a `ReadOnly` error in a read-only
+/// transaction, but cannot write through cursors. Use `Transaction::into_read_write` or
+/// `Db::read_write_transaction` to get `ReadWrite` handles.
+#[derive(Debug)]
+pub enum Unchecked {}
+
+/// Marks handles belonging to a transaction that is known to be read-write.
+#[derive(Debug)]
+pub enum ReadWrite {}
+
+/// What a handle's transaction is statically known to allow: either `Unchecked` or `ReadWrite`.
+pub trait Mode: private::Sealed {}
+
+impl Mode for Unchecked {}
+impl Mode for ReadWrite {}
+
+mod private {
+    pub trait Sealed {}
+
+    impl Sealed for super::Unchecked {}
+    impl Sealed for super::ReadWrite {}
+}
+
+/// A transaction during a database upgrade.
+#[derive(Debug)]
+pub struct TransactionDuringUpgrade<'a> {
+    pub(crate) inner: Transaction<'a>,
+    pub(crate) db: &'a DbDuringUpgrade,
+}
+
+impl<'a> TransactionDuringUpgrade<'a> {
+    /// Get an existing object store, with full access to its data and schema.
+    pub fn object_store(&self, name: &str) -> Result<ObjectStoreDuringUpgrade<'a>, Error> {
+        self.db.existing_object_store(name)
+    }
+}
+
+impl<'a> Deref for TransactionDuringUpgrade<'a> {
+    type Target = Transaction<'a>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A database transaction.
+///
+/// All reads and writes happen within a transaction. The transaction commits automatically once
+/// there are no more outstanding requests against it.
+pub struct Transaction<'db> {
+    pub(crate) inner: web_sys::IdbTransaction,
+    receiver: TReceiver<(), Error>,
+    // These must live as long as the transaction can fire events.
+    _oncomplete: Closure<dyn FnMut()>,
+    _onabort: Closure<dyn FnMut()>,
+    _onerror: Closure<dyn FnMut(web_sys::Event)>,
+    pub(crate) db: PhantomData<&'db Db>,
+}
+
+impl<'db> Transaction<'db> {
+    /// Wrap a raw transaction, listening for the events that end it.
+    pub(crate) fn new(inner: web_sys::IdbTransaction) -> Self {
+        let (sender, receiver) = transaction_channel();
+
+        let complete_sender = sender.clone();
+        let oncomplete = Closure::wrap(Box::new(move || {
+            if !complete_sender.is_completed() {
+                complete_sender.send(Ok(()));
+            }
+        }) as Box<dyn FnMut()>);
+        inner.set_oncomplete(Some(oncomplete.as_ref().unchecked_ref()));
+
+        let abort_sender = sender.clone();
+        let abort_inner = inner.clone();
+        let onabort = Closure::wrap(Box::new(move || {
+            if !abort_sender.is_completed() {
+                let reason = match abort_inner.error() {
+                    Some(e) => e.into(),
+                    // Aborted by a call to `abort`.
+                    None => Error::Abort("transaction was aborted".into()),
+                };
+                abort_sender.send(Err(reason));
+            }
+        }) as Box<dyn FnMut()>);
+        inner.set_onabort(Some(onabort.as_ref().unchecked_ref()));
+
+        // Errors from requests bubble up to the transaction, and will abort it unless handled.
+        let onerror = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            if !sender.is_completed() {
+                let error = event
+                    .target()
+                    .and_then(|target| target.dyn_into::<web_sys::IdbRequest>().ok())
+                    .and_then(|request| request.error().ok())
+                    .and_then(|error| error);
+                let error = match error {
+                    Some(e) => e.into(),
+                    None => Error::Unknown {
+                        name: "Error".to_owned(),
+                        message: "a request in the transaction failed".to_owned(),
+                    },
+                };
+                sender.send(Err(error));
+            }
+        }) as Box<dyn FnMut(web_sys::Event)>);
+        inner.set_onerror(Some(onerror.as_ref().unchecked_ref()));
+
+        Transaction {
+            inner,
+            receiver,
+            _oncomplete: oncomplete,
+            _onabort: onabort,
+            _onerror: onerror,
+            db: PhantomData,
+        }
+    }
+
+    /// The mode this transaction was opened in.
+    ///
+    /// Fails if the browser reports a mode this crate doesn't know about.
+    pub fn mode(&self) -> Result<TransactionMode, Error> {
+        TransactionMode::try_from(self.inner.mode()?)
+    }
+
+    /// The names of the object stores in the scope of this transaction.
+    pub fn object_store_names(&self) -> Vec<String> {
+        to_collection!(self.inner.object_store_names() => Vec<String> : push)
+    }
+
+    /// Get an object store in the scope of this transaction.
+    pub fn object_store<'a>(&'a self, name: &str) -> Result<ObjectStore<'a>, Error> {
+        let inner = self.inner.object_store(name)?;
+        Ok(ObjectStore {
+            inner,
+            db: PhantomData,
+            mode: PhantomData,
+        })
+    }
+
+    /// Abort the transaction, rolling back any changes made in it.
+    pub fn abort(&self) -> Result<(), Error> {
+        Ok(self.inner.abort()?)
+    }
+
+    /// Check that this transaction is read-write, so that handles from it can write through
+    /// cursors.
+    pub fn into_read_write(self) -> Result<ReadWriteTransaction<'db>, Error> {
+        if self.mode()? == TransactionMode::ReadOnly {
+            return Err(Error::ReadOnly(
+                "a read-only transaction cannot be used for writing".into(),
+            ));
+        }
+        Ok(ReadWriteTransaction { inner: self })
+    }
+
+    /// Get a future that resolves once the transaction has committed.
+    ///
+    /// The future fails if the transaction is aborted, either explicitly or because one of its
+    /// requests failed, with the reason reported by the transaction.
+    pub fn done(self) -> TransactionComplete<'db> {
+        TransactionComplete { transaction: self }
+    }
+}
+
+impl<'db> fmt::Debug for Transaction<'db> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Transaction")
+            .field("inner", &self.inner)
+            .finish()
+    }
+}
+
+impl<'db> Drop for Transaction<'db> {
+    fn drop(&mut self) {
+        // Don't leave dangling callbacks on the transaction.
+        self.inner.set_oncomplete(None);
+        self.inner.set_onabort(None);
+        self.inner.set_onerror(None);
+    }
+}
+
+/// A transaction that is known to be read-write.
+#[derive(Debug)]
+pub struct ReadWriteTransaction<'db> {
+    inner: Transaction<'db>,
+}
+
+impl<'db> ReadWriteTransaction<'db> {
+    /// Get an object store in the scope of this transaction, with write access.
+    pub fn object_store<'a>(&'a self, name: &str) -> Result<ObjectStore<'a, ReadWrite>, Error> {
+        let inner = self.inner.inner.object_store(name)?;
+        Ok(ObjectStore {
+            inner,
+            db: PhantomData,
+            mode: PhantomData,
+        })
+    }
+
+    /// Get a future that resolves once the transaction has committed.
+    pub fn done(self) -> TransactionComplete<'db> {
+        self.inner.done()
+    }
+}
+
+impl<'db> Deref for ReadWriteTransaction<'db> {
+    type Target = Transaction<'db>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.inner
+    }
+}
+
+/// A future that resolves when its transaction completes.
+#[derive(Debug)]
+pub struct TransactionComplete<'db> {
+    transaction: Transaction<'db>,
+}
+
+impl<'db> Future for TransactionComplete<'db> {
+    type Output = Result<(), Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        Pin::new(&mut self.transaction.receiver).poll(cx)
+    }
+}
+
+#[test]
+fn test_mode_from_raw() {
+    use web_sys::IdbTransactionMode as Raw;
+    assert_eq!(TransactionMode::try_from(Raw::Readwrite), Ok(TransactionMode::ReadWrite));
+    match TransactionMode::try_from(Raw::Readwriteflush) {
+        Err(Error::Unknown { ref name, .. }) if name == "TypeError" => (),
+        res => panic!("expected a TypeError, got {:?}", res),
+    }
+}
@@ -1,12 +1,62 @@
-use futures::{task, Async, Future, Poll};
+use std::convert::TryFrom;
+use std::future::Future;
+use std::pin::Pin;
 use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::error::Error;
+use crate::key::Key;
+
+/// Convert a request result that is `undefined` when nothing was found into an `Option`.
+pub fn to_option(val: JsValue) -> Result<Option<JsValue>, Error> {
+    if val.is_undefined() {
+        Ok(None)
+    } else {
+        Ok(Some(val))
+    }
+}
+
+/// Convert a request result that is a key into a `Key`.
+pub fn to_key(val: JsValue) -> Result<Key, Error> {
+    Key::try_from(val)
+}
+
+/// Convert a request result that is a key, or `undefined` when nothing was found, into an
+/// `Option<Key>`.
+pub fn to_option_key(val: JsValue) -> Result<Option<Key>, Error> {
+    to_option(val)?.map(Key::try_from).transpose()
+}
+
+/// Convert a request result that is an array of keys into a `Vec<Key>`.
+pub fn to_keys(val: JsValue) -> Result<Vec<Key>, Error> {
+    to_vec(val)?.into_iter().map(Key::try_from).collect()
+}
+
+/// Convert a request result that is a count into a `u32`.
+pub fn to_count(val: JsValue) -> Result<u32, Error> {
+    match val.as_f64() {
+        Some(count) => Ok(count as u32),
+        None => Err(Error::type_error(format!("expected a count, found {:?}", val))),
+    }
+}
+
+/// Convert a request result that is an array into a `Vec`.
+pub fn to_vec(val: JsValue) -> Result<Vec<JsValue>, Error> {
+    let arr = val.dyn_into::<js_sys::Array>()?;
+    let mut list = Vec::with_capacity(arr.length() as usize);
+    for i in 0..arr.length() {
+        list.push(arr.get(i));
+    }
+    Ok(list)
+}
 
 // some helper stuff for the transaction future:
 #[derive(Debug)]
 pub struct Inner<T, E> {
     completed: bool,
     value: Option<Result<T, E>>,
-    task: Option<task::Task>,
+    waker: Option<Waker>,
 }
 
 impl<T, E> Inner<T, E> {
@@ -14,7 +64,7 @@ impl<T, E> Inner<T, E> {
         Inner {
             completed: false,
             value: None,
-            task: None,
+            waker: None,
         }
     }
 }
@@ -35,6 +85,11 @@ pub struct TSender<T, E> {
 }
 
 impl<T, E> TSender<T, E> {
+    /// Whether a value has already been sent down the channel.
+    pub fn is_completed(&self) -> bool {
+        self.inner.lock().unwrap().completed
+    }
+
     pub fn send(&self, value: Result<T, E>) {
         let mut lock = self.inner.lock().unwrap();
 
@@ -42,9 +97,9 @@ impl<T, E> TSender<T, E> {
             lock.completed = true;
             lock.value = Some(value);
 
-            if let Some(task) = lock.task.take() {
+            if let Some(waker) = lock.waker.take() {
                 drop(lock);
-                task.notify();
+                waker.wake();
             }
         } else {
             panic!("Only 1 event down channel");
@@ -58,17 +113,16 @@ pub struct TReceiver<T, E> {
 }
 
 impl<T, E> Future for TReceiver<T, E> {
-    type Item = T;
-    type Error = E;
+    type Output = Result<T, E>;
 
-    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
         let mut lock = self.inner.lock().unwrap();
 
         if lock.completed {
-            lock.value.take().unwrap().map(Async::Ready)
+            Poll::Ready(lock.value.take().unwrap())
         } else {
-            lock.task = Some(task::current());
-            Ok(Async::NotReady)
+            lock.waker = Some(cx.waker().clone());
+            Poll::Pending
         }
     }
 }
@@ -1,30 +1,77 @@
 use wasm_bindgen_test::*;
 
-use futures::Future;
-use indexeddb::object_store::KeyPath;
+use futures::{StreamExt, TryStreamExt};
+use indexeddb::{Error, Key, KeyPath, TransactionMode};
 use wasm_bindgen::*;
 
 wasm_bindgen_test_configure!(run_in_browser);
 
-#[wasm_bindgen_test(async)]
-fn open() -> impl Future<Item = (), Error = JsValue> {
-    indexeddb::open("test", 1, |_old_version, _upgrader| ()).map(|_| ())
+#[wasm_bindgen_test]
+async fn open() -> Result<(), JsValue> {
+    indexeddb::open("test", 1, |_old_version, _upgrader| ()).await?;
+    Ok(())
 }
 
-#[wasm_bindgen_test(async)]
-fn object_store_params() -> impl Future<Item = (), Error = JsValue> {
+#[wasm_bindgen_test]
+async fn open_version_zero() {
+    match indexeddb::open("test_version_zero", 0, |_, _| ()).await {
+        Err(Error::Unknown { ref name, .. }) if name == "TypeError" => (),
+        res => panic!("expected a TypeError, got {:?}", res),
+    }
+}
+
+#[wasm_bindgen_test]
+async fn transaction_without_stores() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_transaction_without_stores", 1, |_, _| ()).await?;
+    match db.transaction(TransactionMode::ReadOnly) {
+        Err(Error::InvalidAccess(_)) => (),
+        res => panic!("expected an InvalidAccessError, got {:?}", res),
+    }
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn upgrade_transaction_after_upgrade() -> Result<(), JsValue> {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let upgrader = Rc::new(RefCell::new(None));
+    let upgrader_copy = upgrader.clone();
+    let _db = indexeddb::open("test_upgrade_transaction_after_upgrade", 1, move |_, upgrader| {
+        assert!(upgrader.transaction().is_ok());
+        upgrader_copy.borrow_mut().replace(upgrader);
+    })
+    .await?;
+    let upgrader = upgrader.borrow_mut().take().unwrap();
+    match upgrader.transaction() {
+        Err(Error::InvalidState(_)) => (),
+        res => panic!("expected an InvalidStateError, got {:?}", res),
+    };
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn unsupported_key_path() {
+    use std::convert::TryFrom;
+    match KeyPath::try_from(JsValue::from(5)) {
+        Err(Error::DataError(_)) => (),
+        res => panic!("expected a DataError, got {:?}", res),
+    }
+}
+
+#[wasm_bindgen_test]
+async fn object_store_params() -> Result<(), JsValue> {
     indexeddb::open("test2", 1, |_, upgrader| {
         let obj_store = upgrader
             .create_object_store("test", KeyPath::None, false)
             .unwrap();
-        assert_eq!(obj_store.key_path(), KeyPath::None);
-        assert_eq!(obj_store.auto_increment(), false);
+        assert_eq!(obj_store.key_path(), Ok(KeyPath::None));
+        assert!(!obj_store.auto_increment());
         drop(obj_store);
         let obj_store = upgrader
             .create_object_store("test2", KeyPath::Single("test".into()), true)
             .unwrap();
-        assert_eq!(obj_store.key_path(), KeyPath::Single("test".into()));
-        assert_eq!(obj_store.auto_increment(), true);
+        assert_eq!(obj_store.key_path(), Ok(KeyPath::Single("test".into())));
+        assert!(obj_store.auto_increment());
         drop(obj_store);
         let obj_store = upgrader
             .create_object_store(
@@ -35,8 +82,845 @@ fn object_store_params() -> impl Future<Item = (), Error = JsValue> {
             .unwrap();
         assert_eq!(
             obj_store.key_path(),
-            KeyPath::Multi(vec!["test".into(), "test2".into()])
+            Ok(KeyPath::Multi(vec!["test".into(), "test2".into()]))
         );
     })
-    .map(|_db| ())
+    .await?;
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn compound_key_path() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_compound_key_path", 1, |_, upgrader| {
+        let store = upgrader
+            .create_object_store("people", &["family_name", "given_name"][..], false)
+            .unwrap();
+        store
+            .create_index("by_age_and_name", vec!["age".into(), "given_name".into()], false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("people")?;
+    assert_eq!(
+        store.key_path(),
+        Ok(KeyPath::Multi(vec!["family_name".into(), "given_name".into()]))
+    );
+    let person = js_sys::Object::new();
+    js_sys::Reflect::set(&person, &"family_name".into(), &"Smith".into())?;
+    js_sys::Reflect::set(&person, &"given_name".into(), &"Ann".into())?;
+    js_sys::Reflect::set(&person, &"age".into(), &JsValue::from(30))?;
+    let index = store.index("by_age_and_name")?;
+    let key = store.put(&person).await?;
+    assert_eq!(key, Key::Array(vec!["Smith".into(), "Ann".into()]));
+    let key = index.get_key(vec![Key::from(30), Key::from("Ann")]).await?;
+    assert_eq!(key, Some(Key::Array(vec!["Smith".into(), "Ann".into()])));
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn index_options() -> Result<(), JsValue> {
+    use indexeddb::IndexOptions;
+    let db = indexeddb::open("test_index_options", 1, |_, upgrader| {
+        let store = upgrader
+            .create_object_store("posts", "id", false)
+            .unwrap();
+        let by_tag = store
+            .create_index("by_tag", "tags", IndexOptions::new().multi_entry(true))
+            .unwrap();
+        assert!(by_tag.multi_entry());
+        assert!(!by_tag.unique());
+        assert_eq!(by_tag.key_path(), Ok(KeyPath::Single("tags".into())));
+        assert_eq!(by_tag.object_store_name(), "posts");
+        let by_slug = store
+            .create_index("by_slug", "slug", IndexOptions::new().unique(true))
+            .unwrap();
+        assert!(by_slug.unique());
+        assert!(!by_slug.multi_entry());
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("posts")?;
+    for (id, tags) in [(1, &["a", "b"][..]), (2, &["b"]), (3, &["c"])] {
+        let post = js_sys::Object::new();
+        let tags: js_sys::Array = tags.iter().copied().map(JsValue::from).collect();
+        js_sys::Reflect::set(&post, &"id".into(), &JsValue::from(id))?;
+        js_sys::Reflect::set(&post, &"slug".into(), &format!("post-{}", id).into())?;
+        js_sys::Reflect::set(&post, &"tags".into(), &tags)?;
+        store.put(&post).await?;
+    }
+    let by_tag = store.index("by_tag")?;
+    let keys = by_tag.get_all_keys("b", None).await?;
+    assert_eq!(keys, vec![Key::from(1), Key::from(2)]);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn rename() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_rename", 1, |_, upgrader| {
+        upgrader.create_object_store("other", "id", false).unwrap();
+        let store = upgrader.create_object_store("old", "id", false).unwrap();
+        store.create_index("by_x", "x", false).unwrap();
+        store.create_index("by_y", "y", false).unwrap();
+        match store.rename("other") {
+            Err(Error::ConstraintError(_)) => (),
+            res => panic!("expected a ConstraintError, got {:?}", res),
+        }
+        store.rename("new").unwrap();
+        assert_eq!(store.name(), "new");
+        let index = store.index("by_x").unwrap();
+        match index.rename("by_y") {
+            Err(Error::ConstraintError(_)) => (),
+            res => panic!("expected a ConstraintError, got {:?}", res),
+        }
+        index.rename("by_z").unwrap();
+        index.rename("by_z").unwrap();
+    })
+    .await?;
+    let mut names = db.object_store_names();
+    names.sort();
+    assert_eq!(names, vec!["new".to_string(), "other".to_string()]);
+    let tx = db.transaction_on_store("new", TransactionMode::ReadOnly)?;
+    let mut index_names: Vec<_> = tx.object_store("new")?.index_names().into_iter().collect();
+    index_names.sort();
+    assert_eq!(index_names, vec!["by_y".to_string(), "by_z".to_string()]);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn existing_object_store() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_existing_object_store", 1, |_, upgrader| {
+        let store = upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+        drop(store.put_with_key(&JsValue::from(1), 1));
+        drop(store);
+        match upgrader.existing_object_store("missing") {
+            Err(Error::NotFound(_)) => (),
+            res => panic!("expected a NotFoundError, got {:?}", res),
+        }
+        let store = upgrader.existing_object_store("store").unwrap();
+        drop(store.put_with_key(&JsValue::from(2), 2));
+        store.create_index("by_value", "", false).unwrap();
+        let tx = upgrader.transaction().unwrap();
+        assert_eq!(tx.mode(), Ok(TransactionMode::VersionChange));
+        let store = tx.object_store("store").unwrap();
+        drop(store.put_with_key(&JsValue::from(3), 3));
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    let store = tx.object_store("store")?;
+    assert!(store.index_names().contains("by_value"));
+    let keys = store.get_all_keys(.., None).await?;
+    assert_eq!(keys, vec![Key::from(1), Key::from(2), Key::from(3)]);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn async_upgrade() -> Result<(), JsValue> {
+    let db = indexeddb::open_with_async_upgrade("test_async_upgrade", 1, |_, upgrader| {
+        Box::pin(async move {
+            let store = upgrader.create_object_store("store", KeyPath::None, false)?;
+            for i in 0..5 {
+                drop(store.put_with_key(&JsValue::from(i), i));
+            }
+            // Double every value, reading them back first.
+            let values = store.get_all(.., None).await?;
+            for (i, value) in values.iter().enumerate() {
+                let doubled = JsValue::from(value.as_f64().unwrap() * 2.0);
+                store.put_with_key(&doubled, i as u32).await?;
+            }
+            assert_eq!(store.count(..).await?, 5);
+            Ok(())
+        })
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    let store = tx.object_store("store")?;
+    let values = store.get_all(.., None).await?;
+    let values: Vec<f64> = values.iter().map(|v| v.as_f64().unwrap()).collect();
+    assert_eq!(values, vec![0.0, 2.0, 4.0, 6.0, 8.0]);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn async_upgrade_after_timer() -> Result<(), JsValue> {
+    let name = "test_async_upgrade_after_timer";
+    let db = indexeddb::open_with_async_upgrade(name, 1, |_, upgrader| {
+        Box::pin(async move {
+            let store = upgrader.create_object_store("store", KeyPath::None, false)?;
+            // With nothing keeping it alive, the transaction would commit during the wait, and
+            // the write after it would fail with a `TransactionInactive` error.
+            sleep(50).await?;
+            store.put_with_key(&JsValue::from("late"), 1).await?;
+            Ok(())
+        })
+    })
+    .await?;
+    // The store used to keep the transaction alive is gone.
+    assert_eq!(db.object_store_names(), vec!["store".to_string()]);
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    let value = tx.object_store("store")?.get(1).await?;
+    assert_eq!(value.and_then(|v| v.as_string()), Some("late".into()));
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn async_upgrade_failure() -> Result<(), JsValue> {
+    let res = indexeddb::open_with_async_upgrade("test_async_upgrade_failure", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+        Box::pin(async { Err(Error::DataError("bad data".into())) })
+    })
+    .await;
+    match res {
+        Err(Error::DataError(ref message)) if message == "bad data" => (),
+        res => panic!("expected the migration's error, got {:?}", res),
+    }
+    // The upgrade was rolled back, so it runs again from scratch.
+    indexeddb::open("test_async_upgrade_failure", 1, |old_version, upgrader| {
+        assert_eq!(old_version, 0);
+        assert!(upgrader.object_store_names().is_empty());
+    })
+    .await?;
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn async_upgrade_request_failure() {
+    let name = "test_async_upgrade_request_failure";
+    let res = indexeddb::open_with_async_upgrade(name, 1, |_, upgrader| {
+        Box::pin(async move {
+            let store = upgrader.create_object_store("store", KeyPath::None, false)?;
+            let value = JsValue::from("value");
+            store.add_with_key(&value, 1).await?;
+            // This aborts the upgrade as soon as it fails.
+            store.add_with_key(&value, 1).await?;
+            Ok(())
+        })
+    })
+    .await;
+    match res {
+        Err(Error::ConstraintError(_)) => (),
+        res => panic!("expected a ConstraintError, got {:?}", res),
+    }
+}
+
+#[wasm_bindgen_test]
+async fn migrations() -> Result<(), JsValue> {
+    use indexeddb::Migrations;
+    let migrations = Migrations::new()
+        .version(1, |db| {
+            db.create_object_store("contact", "id", true)?;
+            Ok(())
+        })
+        .version(2, |db| {
+            let store = db.existing_object_store("contact")?;
+            store.create_index("by_name", "name", false)?;
+            Ok(())
+        });
+    assert_eq!(migrations.latest_version(), 2);
+    let db = migrations.open("test_migrations").await?;
+    assert_eq!(db.version(), 2);
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    let store = tx.object_store("contact")?;
+    assert!(store.index_names().contains("by_name"));
+
+    let res = Migrations::new()
+        .version(1, |_| Ok(()))
+        .version(2, |_| Err(Error::DataError("bad data".into())))
+        .open("test_migrations_failure")
+        .await;
+    match res {
+        Err(Error::DataError(ref message)) if message == "bad data" => (),
+        res => panic!("expected the step's error, got {:?}", res),
+    }
+
+    let res = Migrations::new()
+        .version(0, |_| Ok(()))
+        .open("test_migrations_version_zero")
+        .await;
+    match res {
+        Err(Error::Unknown { ref name, ref message })
+            if name == "TypeError" && message.contains("step 1 ") => (),
+        res => panic!("expected a TypeError naming the step, got {:?}", res),
+    }
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn version_change() -> Result<(), JsValue> {
+    use indexeddb::VersionChange;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    let changes = Rc::new(RefCell::new(Vec::new()));
+    let blocked = Rc::new(RefCell::new(Vec::new()));
+    let (changes_copy, blocked_copy) = (changes.clone(), blocked.clone());
+    let db = indexeddb::open("test_version_change", 1, |_, _| ()).await?;
+    db.on_version_change(move |change| changes_copy.borrow_mut().push(change));
+    // The first connection closes itself, so the upgrade isn't blocked.
+    let upgraded = indexeddb::open("test_version_change", 2, |_, _| ())
+        .on_blocked(move |change| blocked_copy.borrow_mut().push(change))
+        .await?;
+    assert_eq!(upgraded.version(), 2);
+    let change = VersionChange {
+        old_version: 1,
+        new_version: Some(2),
+    };
+    assert_eq!(*changes.borrow(), vec![change]);
+    assert!(blocked.borrow().is_empty());
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn close() -> Result<(), JsValue> {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    let blocked = Rc::new(Cell::new(0));
+    let blocked_copy = blocked.clone();
+    let db = indexeddb::open("test_close", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    // Keep the connection open until the upgrade is blocked, then close it by hand.
+    db.set_close_on_version_change(false);
+    let db = Rc::new(db);
+    let db_copy = db.clone();
+    let upgraded = indexeddb::open("test_close", 2, |_, _| ())
+        .on_blocked(move |_| {
+            blocked_copy.set(blocked_copy.get() + 1);
+            db_copy.close();
+        })
+        .await?;
+    assert_eq!(upgraded.version(), 2);
+    match db.transaction(TransactionMode::ReadOnly) {
+        Err(Error::InvalidState(_)) => (),
+        res => panic!("expected an InvalidState error, got {:?}", res),
+    }
+    assert_eq!(blocked.get(), 1);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn drop_blocked_open() -> Result<(), JsValue> {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    let name = "test_drop_blocked_open";
+    let db = indexeddb::open(name, 1, |_, upgrader| {
+        upgrader.create_object_store("v1", "id", false).unwrap();
+    })
+    .await?;
+    db.set_close_on_version_change(false);
+    let blocked = Rc::new(Cell::new(false));
+    let blocked_copy = blocked.clone();
+    let open = indexeddb::open(name, 2, |_, upgrader| {
+        upgrader.create_object_store("v2", "id", false).unwrap();
+    })
+    .on_blocked(move |_| blocked_copy.set(true));
+    while !blocked.get() {
+        sleep(10).await?;
+    }
+    // Give up on the open, then unblock it.
+    drop(open);
+    drop(db);
+    let db = indexeddb::Factory::get()?.open_latest(name).await?;
+    assert_eq!(db.version(), 1);
+    assert_eq!(db.object_store_names(), vec!["v1".to_string()]);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn close_on_drop() -> Result<(), JsValue> {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    let blocked = Rc::new(Cell::new(0));
+    let db = indexeddb::open("test_close_on_drop", 1, |_, _| ()).await?;
+    // Dropping the handle closes the connection even if it wouldn't close itself.
+    db.set_close_on_version_change(false);
+    drop(db);
+    let blocked_copy = blocked.clone();
+    let upgraded = indexeddb::open("test_close_on_drop", 2, |_, _| ())
+        .on_blocked(move |_| blocked_copy.set(blocked_copy.get() + 1))
+        .await?;
+    assert_eq!(upgraded.version(), 2);
+    upgraded.set_close_on_version_change(false);
+    drop(upgraded);
+    let blocked_copy = blocked.clone();
+    indexeddb::Factory::get()?
+        .delete_database("test_close_on_drop")
+        .on_blocked(move |_| blocked_copy.set(blocked_copy.get() + 1))
+        .await?;
+    assert_eq!(blocked.get(), 0);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn factory() -> Result<(), JsValue> {
+    use indexeddb::{DatabaseInfo, Factory};
+    let factory = Factory::get()?;
+    let db = indexeddb::open("test_factory", 3, |_, _| ()).await?;
+    let latest = factory.open_latest("test_factory").await?;
+    assert_eq!(latest.version(), 3);
+    drop(db);
+
+    match factory.databases().await {
+        Ok(databases) => assert!(databases.contains(&DatabaseInfo {
+            name: "test_factory".into(),
+            version: 3,
+        })),
+        // Not every browser can list databases.
+        Err(Error::NotFound(_)) => (),
+        Err(e) => panic!("failed to list databases: {:?}", e),
+    }
+
+    // The open connection closes itself, so the delete isn't blocked.
+    factory
+        .delete_database("test_factory")
+        .on_blocked(|_| panic!("the delete should not be blocked"))
+        .await?;
+    assert!(latest.transaction(TransactionMode::ReadOnly).is_err());
+
+    match factory.open_latest("test_factory").await {
+        Err(Error::NotFound(_)) => (),
+        res => panic!("expected a NotFound error, got {:?}", res),
+    }
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn transaction() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_transaction", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("contact", "id", true)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    assert_eq!(tx.mode(), Ok(TransactionMode::ReadOnly));
+    assert_eq!(tx.object_store_names(), vec!["contact".to_string()]);
+    assert_eq!(tx.object_store("contact")?.name(), "contact");
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn transaction_on() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_transaction_on", 1, |_, upgrader| {
+        upgrader.create_object_store("contact", "id", true).unwrap();
+        upgrader.create_object_store("note", "id", true).unwrap();
+        upgrader.create_object_store("other", "id", true).unwrap();
+    })
+    .await?;
+    let tx = db.transaction_on(&["contact", "note"], TransactionMode::ReadWrite)?;
+    assert_eq!(
+        tx.object_store_names(),
+        vec!["contact".to_string(), "note".to_string()]
+    );
+    assert!(tx.object_store("other").is_err());
+    drop(tx);
+    let tx = db.transaction_on_store("other", TransactionMode::ReadOnly)?;
+    assert_eq!(tx.object_store_names(), vec!["other".to_string()]);
+    match db.transaction_on(&["missing"], TransactionMode::ReadOnly) {
+        Err(Error::NotFound(_)) => (),
+        res => panic!("expected a NotFoundError, got {:?}", res),
+    }
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn transaction_abort() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_transaction_abort", 1, |_, upgrader| {
+        upgrader.create_object_store("contact", "id", true).unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    tx.abort()?;
+    assert!(tx.done().await.is_err());
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn transaction_done() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_transaction_done", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    // Requests run whether or not they are awaited.
+    drop(store.put_with_key(&JsValue::from("a"), 1));
+    drop(store.put_with_key(&JsValue::from("b"), 2));
+    drop(store);
+    tx.done().await?;
+
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    let store = tx.object_store("store")?;
+    assert_eq!(store.get(1).await?.and_then(|v| v.as_string()), Some("a".into()));
+    assert_eq!(store.count(..).await?, 2);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn transaction_done_failure() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_transaction_done_failure", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    drop(store.add_with_key(&JsValue::from("a"), 1));
+    drop(store.add_with_key(&JsValue::from("b"), 1));
+    drop(store);
+    match tx.done().await {
+        Err(Error::ConstraintError(_)) => (),
+        res => panic!("expected a ConstraintError, got {:?}", res),
+    }
+
+    // The failed transaction was rolled back.
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    assert_eq!(tx.object_store("store")?.count(..).await?, 0);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn put_and_add() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_put_and_add", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("generated", KeyPath::None, true)
+            .unwrap();
+        upgrader
+            .create_object_store("explicit", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let generated = tx.object_store("generated")?;
+    let explicit = tx.object_store("explicit")?;
+    let value = JsValue::from("value");
+    assert_eq!(generated.add(&value).await?, Key::Number(1.0));
+    assert_eq!(generated.put(&value).await?, Key::Number(2.0));
+    assert_eq!(
+        explicit.put_with_key(&value, "key").await?,
+        Key::String("key".into())
+    );
+    match explicit.put(&value).await {
+        Err(Error::DataError(_)) => (),
+        res => panic!("expected a DataError, got {:?}", res),
+    }
+    // out-of-line keys with a generator can be given explicitly
+    assert_eq!(generated.add_with_key(&value, 5).await?, Key::Number(5.0));
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn constraint_error() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_constraint_error", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+        match upgrader.create_object_store("store", KeyPath::None, false) {
+            Err(Error::ConstraintError(_)) => (),
+            res => panic!("expected a ConstraintError, got {:?}", res),
+        }
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    let value = JsValue::from("value");
+    store.add_with_key(&value, 1).await?;
+    match store.add_with_key(&value, 1).await {
+        Err(Error::ConstraintError(_)) => (),
+        res => panic!("expected a ConstraintError, got {:?}", res),
+    }
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn get() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_get", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    for i in 0..10 {
+        store.put_with_key(&JsValue::from(i * 10), i).await?;
+    }
+    assert_eq!(store.get(3).await?.unwrap().as_f64(), Some(30.0));
+    assert!(store.get(20).await?.is_none());
+    assert_eq!(store.get_key(3).await?, Some(Key::Number(3.0)));
+    assert_eq!(store.get_all(.., Some(4)).await?.len(), 4);
+    assert_eq!(store.get_all_keys(.., None).await?.len(), 10);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn delete_and_count() -> Result<(), JsValue> {
+    let db = indexeddb::open("test_delete_and_count", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    for i in 0..10 {
+        store.put_with_key(&JsValue::from(i), i).await?;
+    }
+    store.delete(3).await?;
+    assert_eq!(store.count(..).await?, 9);
+    store.clear().await?;
+    assert_eq!(store.count(..).await?, 0);
+
+    let db = indexeddb::open("test_delete_and_count", 1, |_, _| ()).await?;
+    let tx = db.transaction(TransactionMode::ReadOnly)?;
+    let store = tx.object_store("store")?;
+    match store.delete(1).await {
+        Err(Error::ReadOnly(_)) => (),
+        res => panic!("expected a ReadOnlyError, got {:?}", res),
+    }
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+fn key_round_trip() {
+    use std::convert::TryFrom;
+    let keys = vec![
+        Key::Number(-1.5),
+        Key::Date(1_000_000.0),
+        Key::String("hello".into()),
+        Key::Binary(vec![0, 1, 255]),
+        Key::Array(vec![Key::Number(1.0), Key::Array(vec!["nested".into()])]),
+    ];
+    for key in keys {
+        let val = JsValue::try_from(&key).unwrap();
+        assert_eq!(Key::try_from(val).unwrap(), key);
+    }
+    assert!(Key::try_from(JsValue::from_f64(f64::NAN)).is_err());
+    let nested = Key::Array(vec![Key::Number(1.0), Key::Date(f64::NAN)]);
+    match JsValue::try_from(&nested) {
+        Err(Error::DataError(_)) => (),
+        res => panic!("expected a DataError, got {:?}", res),
+    }
+    assert!(Key::try_from(JsValue::TRUE).is_err());
+}
+
+#[wasm_bindgen_test]
+async fn key_range() -> Result<(), JsValue> {
+    use indexeddb::KeyRange;
+    let db = indexeddb::open("test_key_range", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    for i in 0..10 {
+        store.put_with_key(&JsValue::from(i), i).await?;
+    }
+    assert_eq!(
+        store.get_all_keys(3..6, None).await?,
+        vec![Key::from(3), Key::from(4), Key::from(5)]
+    );
+    assert_eq!(store.count(..=4).await?, 5);
+    let value = store.get(KeyRange::lower_bound(8, true)).await?;
+    assert_eq!(value.unwrap().as_f64(), Some(9.0));
+    store.delete_range(5..).await?;
+    assert_eq!(store.count(..).await?, 5);
+
+    use std::convert::TryFrom;
+    let raw = web_sys::IdbKeyRange::try_from(&KeyRange::from(3..6))?;
+    assert_eq!(raw.lower()?.as_f64(), Some(3.0));
+    assert_eq!(raw.upper()?.as_f64(), Some(6.0));
+    assert!(!raw.lower_open());
+    assert!(raw.upper_open());
+    match web_sys::IdbKeyRange::try_from(&KeyRange::from(..)) {
+        Err(Error::DataError(_)) => (),
+        res => panic!("expected a DataError, got {:?}", res),
+    }
+    Ok(())
+}
+
+/// Wait for `millis` milliseconds.
+fn sleep(millis: i32) -> wasm_bindgen_futures::JsFuture {
+    let timer = js_sys::Promise::new(&mut |resolve, _| {
+        web_sys::window()
+            .unwrap()
+            .set_timeout_with_callback_and_timeout_and_arguments_0(&resolve, millis)
+            .unwrap();
+    });
+    wasm_bindgen_futures::JsFuture::from(timer)
+}
+
+/// A small deterministic generator of arbitrary keys.
+struct KeyGen(u32);
+
+impl KeyGen {
+    fn next(&mut self) -> u32 {
+        // xorshift32
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0
+    }
+
+    fn key(&mut self, depth: u32) -> Key {
+        let kinds = if depth > 2 { 4 } else { 5 };
+        match self.next() % kinds {
+            0 => Key::Number((self.next() % 7) as f64 - 3.0),
+            1 => Key::Date((self.next() % 5) as f64 * 1000.0),
+            2 => {
+                let chars = ['a', 'b', '\u{e9}', '\u{ff61}', '\u{1f600}'];
+                let len = self.next() % 4;
+                Key::String((0..len).map(|_| chars[self.next() as usize % 5]).collect())
+            }
+            3 => {
+                let len = self.next() % 4;
+                Key::Binary((0..len).map(|_| (self.next() % 3) as u8 * 127).collect())
+            }
+            _ => {
+                let len = self.next() % 3;
+                Key::Array((0..len).map(|_| self.key(depth + 1)).collect())
+            }
+        }
+    }
+}
+
+#[wasm_bindgen_test]
+fn factory_from_raw() {
+    let raw = web_sys::window().unwrap().indexed_db().unwrap().unwrap();
+    let factory = indexeddb::Factory::from_raw(raw);
+    let ordering = factory.cmp(&Key::Number(1.0), &Key::Number(2.0)).unwrap();
+    assert_eq!(ordering, std::cmp::Ordering::Less);
+}
+
+#[wasm_bindgen_test]
+fn key_ordering_matches_browser() {
+    let factory = indexeddb::Factory::get().unwrap();
+    let mut gen = KeyGen(0x1234_5678);
+    for _ in 0..5000 {
+        let first = gen.key(0);
+        let second = gen.key(0);
+        assert_eq!(
+            first.cmp(&second),
+            factory.cmp(&first, &second).unwrap(),
+            "comparing {:?} with {:?}",
+            first,
+            second
+        );
+    }
+}
+
+#[wasm_bindgen_test]
+async fn cursor() -> Result<(), JsValue> {
+    use indexeddb::CursorDirection;
+    let db = indexeddb::open("test_cursor", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    for i in 0..10 {
+        store.put_with_key(&JsValue::from(i * 10), i).await?;
+    }
+    let entries: Vec<_> = store
+        .open_cursor(2..5, CursorDirection::Prev)
+        .map_ok(|entry| {
+            assert_eq!(entry.key(), entry.primary_key());
+            (entry.key().clone(), entry.value().unwrap().as_f64().unwrap())
+        })
+        .try_collect()
+        .await?;
+    assert_eq!(
+        entries,
+        vec![(Key::from(4), 40.0), (Key::from(3), 30.0), (Key::from(2), 20.0)]
+    );
+    let keys: Vec<_> = store
+        .open_key_cursor(.., CursorDirection::Next)
+        .map_ok(|entry| {
+            assert!(entry.value().is_none());
+            entry.key().clone()
+        })
+        .try_collect()
+        .await?;
+    assert_eq!(keys, (0..10).map(Key::from).collect::<Vec<_>>());
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn cursor_update_and_delete() -> Result<(), JsValue> {
+    use indexeddb::CursorDirection;
+    let db = indexeddb::open("test_cursor_update_and_delete", 1, |_, upgrader| {
+        upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+    })
+    .await?;
+    assert!(db
+        .transaction(TransactionMode::ReadOnly)?
+        .into_read_write()
+        .is_err());
+    let tx = db.read_write_transaction()?;
+    let store = tx.object_store("store")?;
+    for i in 0..10 {
+        store.put_with_key(&JsValue::from(i), i).await?;
+    }
+    let mut cursor = store.open_cursor(.., CursorDirection::Next);
+    while let Some(entry) = cursor.try_next().await? {
+        let n = entry.value().unwrap().as_f64().unwrap();
+        if (n as u32).is_multiple_of(2) {
+            entry.delete().await?;
+        } else {
+            entry.update(&JsValue::from(n * 100.0)).await?;
+        }
+    }
+    let values = store.get_all(.., None).await?;
+    let values: Vec<f64> = values.iter().map(|v| v.as_f64().unwrap()).collect();
+    assert_eq!(values, vec![100.0, 300.0, 500.0, 700.0, 900.0]);
+    Ok(())
+}
+
+#[wasm_bindgen_test]
+async fn cursor_positioning() -> Result<(), JsValue> {
+    use indexeddb::CursorDirection;
+    let db = indexeddb::open("test_cursor_positioning", 1, |_, upgrader| {
+        let store = upgrader
+            .create_object_store("store", KeyPath::None, false)
+            .unwrap();
+        store.create_index("by_group", "group", false).unwrap();
+    })
+    .await?;
+    let tx = db.transaction(TransactionMode::ReadWrite)?;
+    let store = tx.object_store("store")?;
+    let index = store.index("by_group")?;
+    // 20 records, in 4 groups of 5.
+    for i in 0..20 {
+        let value = js_sys::Object::new();
+        js_sys::Reflect::set(&value, &"group".into(), &JsValue::from(i / 5))?;
+        store.put_with_key(&value, i).await?;
+    }
+    let mut cursor = store.open_key_cursor(.., CursorDirection::Next);
+    assert_eq!(cursor.next().await.unwrap()?.key(), &Key::from(0));
+    cursor.advance(3)?;
+    assert_eq!(cursor.next().await.unwrap()?.key(), &Key::from(3));
+    cursor.continue_to(15)?;
+    assert_eq!(cursor.next().await.unwrap()?.key(), &Key::from(15));
+    drop(cursor);
+
+    let page = index.page_after(1, 7, 4).await?;
+    let keys: Vec<_> = page.iter().map(|e| e.primary_key().clone()).collect();
+    assert_eq!(keys, (8..12).map(Key::from).collect::<Vec<_>>());
+    assert!(index.page_after(3, 19, 4).await?.is_empty());
+    Ok(())
 }